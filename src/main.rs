@@ -10,24 +10,74 @@ extern crate serde_json;
 extern crate term_table;
 
 extern crate alphavantage;
+extern crate reqwest;
 
 extern crate ansi_term;
 
+extern crate chrono;
+
+extern crate libc;
+
+// The dashboard drives the terminal through termios, which only unix systems have
+#[cfg(unix)]
+mod tui;
+
 use term_table::cell::{Alignment, Cell};
 use term_table::row::Row;
 use term_table::Table;
 
-use std::collections::HashMap;
-use std::fmt::{Debug, Formatter};
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use alphavantage::time_series::TimeSeries;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use ansi_term::Colour::{self, Green, Red};
+use ansi_term::Style;
+
+use chrono::{DateTime, Local, NaiveDate};
+
+const ALPHAVANTAGE_URL: &str = "https://www.alphavantage.co/query";
+const API_KEY_VAR: &str = "ALPHAVANTAGE_API_KEY";
+const API_URL_VAR: &str = "STOCKO_API_URL";
+const DATA_PATH_VAR: &str = "STOCKO_DATA";
+const RETRIES_VAR: &str = "STOCKO_RETRIES";
+
+const DATA_FILE_NAME: &str = "stocko_data.json";
+const CONFIG_FILE_NAME: &str = "stocko_config.json";
+
+/// Tolerance used when comparing share quantities, which can be fractional
+const SHARE_EPSILON: f64 = 1e-6;
 
-use ansi_term::Colour::{Green, Red};
+const SPARKLINE_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPARKLINE_LENGTH: usize = 10;
+const DEFAULT_PRECISION: usize = 2;
+const DEFAULT_MOVERS_LIMIT: usize = 5;
+/// Shortest `--refresh` interval, which keeps a small portfolio within AlphaVantage's rate limit
+const MIN_REFRESH_SECS: u64 = 60;
+const DEFAULT_HISTORY_DAYS: usize = 30;
+
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_CONCURRENCY: usize = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Version written to the data file, bumped whenever its layout changes
+const DATA_VERSION: u32 = 1;
+const DEFAULT_ACCOUNT: &str = "default";
+const DEFAULT_BENCHMARK: &str = "SPY";
+
+/// Account selected with `--account`, which load_data and save_data operate on
+static ACCOUNT: OnceLock<String> = OnceLock::new();
 
 macro_rules! mapStockoErr {
     ($s:expr, $e:expr) => {
@@ -35,15 +85,52 @@ macro_rules! mapStockoErr {
     };
 }
 
+#[derive(Clone)]
 enum StockoError {
     SaveDataError(String),
     ReadDataError(String),
     AlphaVantageError(String),
     InvalidExchange,
-    InvalidShareQuantity { symbol: String, shares: u32 },
+    InvalidShareQuantity { symbol: String, shares: f64 },
+    InvalidCollection(String),
+    ReadInputError(String),
+    InvalidSplitRatio(String),
+    SymbolNotInPortfolio(String),
+    InvalidSymbol { symbol: String },
+    ApiServerError(String),
+    RateLimited(String),
+    DeserializationError(String),
+    TerminalError(String),
+    ReadConfigError(String),
+    MissingApiKey,
+    SymbolNotInWatchlist(String),
+    InsufficientData { symbol: String },
+    InvalidDate(String),
+    InvalidCurrency(String),
+    InvalidOrderIndex { symbol: String, index: usize },
+    OversoldPosition { symbol: String },
+    UnknownSymbol { symbol: String },
+    UntrackedSymbol(String),
+    SymbolNotFound(String),
+    SymbolExists(String),
+    ExportError(String),
+    NoStoredPrice(String),
+    ZeroShareOrder { symbol: String },
+    InvalidPrice(f64),
+    ImportError(String),
+    InvalidImportRow { line: usize, cause: String },
+    ClearPortfolioWithoutForce,
+    UnsupportedDataVersion(u32),
+    UnsupportedFormat(String),
 }
 
 impl Debug for StockoError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Display for StockoError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match *self {
             StockoError::SaveDataError(ref e) => {
@@ -63,23 +150,380 @@ impl Debug for StockoError {
                 "You do not have {} shares of {} in your portfolio",
                 shares, symbol
             ),
+            StockoError::InvalidCollection(ref name) => write!(
+                f,
+                "Invalid collection {}. Expected one of portfolio, watchlist, archive or all",
+                name
+            ),
+            StockoError::ReadInputError(ref e) => write!(f, "Failed to read input. Cause: {}", e),
+            StockoError::InvalidSplitRatio(ref ratio) => write!(
+                f,
+                "Invalid split ratio {}. Expected the form new:old, e.g. 2:1",
+                ratio
+            ),
+            StockoError::SymbolNotInPortfolio(ref symbol) => {
+                write!(f, "{} is not in your portfolio", symbol)
+            }
+            StockoError::InvalidSymbol { ref symbol } => write!(
+                f,
+                "AlphaVantage does not recognize the symbol {}. Check the symbol and exchange",
+                symbol
+            ),
+            StockoError::ApiServerError(ref status) => write!(
+                f,
+                "AlphaVantage is having problems right now, try again later. Status: {}",
+                status
+            ),
+            StockoError::RateLimited(ref note) => write!(
+                f,
+                "Too many requests have been made to AlphaVantage, wait a minute and try again. Note: {}",
+                note
+            ),
+            StockoError::DeserializationError(ref e) => write!(
+                f,
+                "Failed to understand the response from AlphaVantage. Cause: {}",
+                e
+            ),
+            StockoError::TerminalError(ref e) => {
+                write!(f, "Failed to set up the terminal. Cause: {}", e)
+            }
+            StockoError::ReadConfigError(ref e) => {
+                write!(f, "Failed to read stocko_config.json. Cause: {}", e)
+            }
+            StockoError::MissingApiKey => write!(
+                f,
+                "No AlphaVantage API key found. Set the {} environment variable or add an \
+                 \"api_key\" entry to {}. A free key can be requested at \
+                 https://www.alphavantage.co/support/#api-key",
+                API_KEY_VAR,
+                get_config_file_path().display()
+            ),
+            StockoError::SymbolNotInWatchlist(ref symbol) => {
+                write!(f, "{} is not in your watch list", symbol)
+            }
+            StockoError::InsufficientData { ref symbol } => {
+                write!(f, "AlphaVantage has no price history for {}", symbol)
+            }
+            StockoError::InvalidDate(ref date) => {
+                write!(f, "{} is not a valid date, use YYYY-MM-DD", date)
+            }
+            StockoError::InvalidCurrency(ref currency) => {
+                write!(f, "{} is not a supported currency, use CAD, USD, GBP or AUD", currency)
+            }
+            StockoError::InvalidOrderIndex { ref symbol, index } => {
+                write!(f, "{} has no order at index {}", symbol, index)
+            }
+            StockoError::UntrackedSymbol(ref symbol) => {
+                write!(f, "{} is not in your portfolio or watch list", symbol)
+            }
+            StockoError::SymbolNotFound(ref symbol) => write!(
+                f,
+                "{} is not in your portfolio, watch list or archive",
+                symbol
+            ),
+            StockoError::SymbolExists(ref symbol) => write!(f, "{} is already in use", symbol),
+            StockoError::UnsupportedDataVersion(version) => write!(
+                f,
+                "The data file is version {}, this stocko only reads up to version {}",
+                version, DATA_VERSION
+            ),
+            StockoError::ClearPortfolioWithoutForce => write!(
+                f,
+                "Clearing the portfolio removes open positions, pass --force to do it anyway"
+            ),
+            StockoError::ExportError(ref e) => write!(f, "Failed to write the export. Cause: {}", e),
+            StockoError::UnsupportedFormat(ref format) => {
+                write!(f, "Export only writes csv, not {}", format)
+            }
+            StockoError::ZeroShareOrder { ref symbol } => write!(
+                f,
+                "An order for {} needs a number of shares other than 0",
+                symbol
+            ),
+            StockoError::InvalidPrice(price) => write!(
+                f,
+                "Invalid price {}. The price must be greater than 0",
+                price
+            ),
+            StockoError::NoStoredPrice(ref symbol) => write!(
+                f,
+                "No price has been stored for {} yet. Run list while online first",
+                symbol
+            ),
+            StockoError::ImportError(ref e) => write!(f, "Failed to read the import. Cause: {}", e),
+            StockoError::InvalidImportRow { line, ref cause } => {
+                write!(f, "Line {} could not be imported. Cause: {}", line, cause)
+            }
+            StockoError::UnknownSymbol { ref symbol } => write!(
+                f,
+                "No listing matches the symbol {}. Check the symbol and exchange",
+                symbol
+            ),
+            StockoError::OversoldPosition { ref symbol } => write!(
+                f,
+                "That change would leave more shares of {} sold than bought",
+                symbol
+            ),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl StockoError {
+    /// Stable identifier for the error which scripts can match on
+    fn kind(&self) -> &'static str {
+        match *self {
+            StockoError::SaveDataError(_) => "SaveDataError",
+            StockoError::ReadDataError(_) => "ReadDataError",
+            StockoError::AlphaVantageError(_) => "AlphaVantageError",
+            StockoError::InvalidExchange => "InvalidExchange",
+            StockoError::InvalidShareQuantity { .. } => "InvalidShareQuantity",
+            StockoError::InvalidCollection(_) => "InvalidCollection",
+            StockoError::ReadInputError(_) => "ReadInputError",
+            StockoError::InvalidSplitRatio(_) => "InvalidSplitRatio",
+            StockoError::SymbolNotInPortfolio(_) => "SymbolNotInPortfolio",
+            StockoError::InvalidSymbol { .. } => "InvalidSymbol",
+            StockoError::ApiServerError(_) => "ApiServerError",
+            StockoError::RateLimited(_) => "RateLimited",
+            StockoError::DeserializationError(_) => "DeserializationError",
+            StockoError::TerminalError(_) => "TerminalError",
+            StockoError::ReadConfigError(_) => "ReadConfigError",
+            StockoError::MissingApiKey => "MissingApiKey",
+            StockoError::SymbolNotInWatchlist(_) => "SymbolNotInWatchlist",
+            StockoError::InsufficientData { .. } => "InsufficientData",
+            StockoError::InvalidDate(_) => "InvalidDate",
+            StockoError::InvalidCurrency(_) => "InvalidCurrency",
+            StockoError::InvalidOrderIndex { .. } => "InvalidOrderIndex",
+            StockoError::OversoldPosition { .. } => "OversoldPosition",
+            StockoError::UnknownSymbol { .. } => "UnknownSymbol",
+            StockoError::UntrackedSymbol(_) => "UntrackedSymbol",
+            StockoError::SymbolNotFound(_) => "SymbolNotFound",
+            StockoError::SymbolExists(_) => "SymbolExists",
+            StockoError::ExportError(_) => "ExportError",
+            StockoError::ZeroShareOrder { .. } => "ZeroShareOrder",
+            StockoError::InvalidPrice(_) => "InvalidPrice",
+            StockoError::NoStoredPrice(_) => "NoStoredPrice",
+            StockoError::ImportError(_) => "ImportError",
+            StockoError::InvalidImportRow { .. } => "InvalidImportRow",
+            StockoError::ClearPortfolioWithoutForce => "ClearPortfolioWithoutForce",
+            StockoError::UnsupportedDataVersion(_) => "UnsupportedDataVersion",
+            StockoError::UnsupportedFormat(_) => "UnsupportedFormat",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    /// Errors which may go away if the same request is made again a little later
+    fn is_transient(&self) -> bool {
+        matches!(
+            *self,
+            StockoError::AlphaVantageError(_)
+                | StockoError::ApiServerError(_)
+                | StockoError::RateLimited(_)
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorReport {
+    error: String,
+    kind: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorMode {
+    Enabled,
+    Disabled,
+}
+
+impl ColorMode {
+    /// Colour is turned off by `--no-color` or by setting `NO_COLOR` to anything non-empty
+    fn from_flag(no_color: bool) -> ColorMode {
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty());
+        if no_color || no_color_env {
+            ColorMode::Disabled
+        } else {
+            ColorMode::Enabled
+        }
+    }
+
+    fn paint(self, colour: Colour, text: String) -> String {
+        match self {
+            ColorMode::Enabled => colour.paint(text).to_string(),
+            ColorMode::Disabled => text,
+        }
+    }
+
+    fn bold(self, text: String) -> String {
+        match self {
+            ColorMode::Enabled => Style::new().bold().paint(text).to_string(),
+            ColorMode::Disabled => text,
+        }
+    }
+}
+
+/// How tables and numbers are printed, set by `--no-color`, `--plain` and `--precision`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RenderOptions {
+    color: ColorMode,
+    /// Tab separated columns instead of a bordered table
+    plain: bool,
+    /// Decimal places of prices and amounts
+    precision: usize,
+}
+
+impl RenderOptions {
+    fn paint(self, colour: Colour, text: String) -> String {
+        self.color.paint(colour, text)
+    }
+
+    fn bold(self, text: String) -> String {
+        self.color.bold(text)
+    }
+
+    fn number(self, value: f64) -> String {
+        format!("{:.*}", self.precision, value)
+    }
+
+    fn table(self, mut table: Table) -> String {
+        if !self.plain {
+            return table.as_string();
+        }
+
+        // A cell spanning several columns is padded with empty fields to keep them lined up,
+        // titles are the only cell on their row and are left as they are
+        let mut lines = Vec::new();
+        for row in &table.rows {
+            let mut fields = Vec::new();
+            for cell in &row.cells {
+                fields.push(cell.data.to_string());
+                if row.cells.len() > 1 {
+                    fields.extend((1..cell.col_span).map(|_| String::new()));
+                }
+            }
+            lines.push(fields.join("\t"));
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Table,
+    Json,
+    /// Only used by export, everything else prints tables instead
+    Csv,
+}
+
+impl OutputFormat {
+    fn from_name(name: Option<&str>) -> OutputFormat {
+        match name {
+            Some("json") => OutputFormat::Json,
+            Some("csv") => OutputFormat::Csv,
+            _ => OutputFormat::Table,
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 enum Currency {
     CAD,
     USD,
+    GBP,
+    AUD,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl Currency {
+    fn from_name(name: &str) -> Result<Currency, StockoError> {
+        match name.to_uppercase().as_ref() {
+            "CAD" => Ok(Currency::CAD),
+            "USD" => Ok(Currency::USD),
+            "GBP" => Ok(Currency::GBP),
+            "AUD" => Ok(Currency::AUD),
+            _ => Err(StockoError::InvalidCurrency(String::from(name))),
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Currency::CAD => "CAD",
+            Currency::USD => "USD",
+            Currency::GBP => "GBP",
+            Currency::AUD => "AUD",
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 enum Exchange {
     TSX,
     TSXV,
     NYSE,
+    NASDAQ,
+    LSE,
+    ASX,
 }
 
+struct ExchangeInfo {
+    exchange: Exchange,
+    /// Name given to `--exchange`, matched case insensitively
+    name: &'static str,
+    /// Appended to symbols listed on the exchange when requesting them from AlphaVantage
+    suffix: &'static str,
+    /// Suffix AlphaVantage's symbol search gives listings on the exchange instead
+    search_suffix: &'static str,
+    currency: Currency,
+}
+
+/// Every supported exchange, both parsing and the suffix lookup go through this
+static EXCHANGES: [ExchangeInfo; 6] = [
+    ExchangeInfo {
+        exchange: Exchange::TSX,
+        name: "TSX",
+        suffix: ".TO",
+        search_suffix: ".TRT",
+        currency: Currency::CAD,
+    },
+    ExchangeInfo {
+        exchange: Exchange::TSXV,
+        name: "TSXV",
+        suffix: ".V",
+        search_suffix: ".TRV",
+        currency: Currency::CAD,
+    },
+    ExchangeInfo {
+        exchange: Exchange::NYSE,
+        name: "NYSE",
+        suffix: "",
+        search_suffix: "",
+        currency: Currency::USD,
+    },
+    ExchangeInfo {
+        exchange: Exchange::NASDAQ,
+        name: "NASDAQ",
+        suffix: "",
+        search_suffix: "",
+        currency: Currency::USD,
+    },
+    ExchangeInfo {
+        exchange: Exchange::LSE,
+        name: "LSE",
+        suffix: ".L",
+        search_suffix: ".LON",
+        currency: Currency::GBP,
+    },
+    ExchangeInfo {
+        exchange: Exchange::ASX,
+        name: "ASX",
+        suffix: ".AX",
+        search_suffix: ".AX",
+        currency: Currency::AUD,
+    },
+];
+
 impl Default for Exchange {
     fn default() -> Self {
         return Exchange::NYSE;
@@ -89,14 +533,30 @@ impl Default for Exchange {
 impl Exchange {
     fn from_symbol(symbol: Option<&str>) -> Result<Exchange, StockoError> {
         if let Some(symbol) = symbol {
-            return match symbol.to_lowercase().as_ref() {
-                "tsx" => Ok(Exchange::TSX),
-                "tsxv" => Ok(Exchange::TSXV),
-                "nsye" => Ok(Exchange::NYSE),
-                _ => Err(StockoError::InvalidExchange),
-            };
+            return EXCHANGES
+                .iter()
+                .find(|x| x.name.eq_ignore_ascii_case(symbol))
+                .map(|x| x.exchange.clone())
+                .ok_or(StockoError::InvalidExchange);
         }
-        return Ok(Exchange::NYSE);
+        return Ok(Exchange::default());
+    }
+
+    fn info(&self) -> &'static ExchangeInfo {
+        EXCHANGES.iter().find(|x| x.exchange == *self).unwrap()
+    }
+
+    fn name(&self) -> &'static str {
+        self.info().name
+    }
+
+    fn suffix(&self) -> &'static str {
+        self.info().suffix
+    }
+
+    /// Currency the exchange quotes its prices in
+    fn currency(&self) -> Currency {
+        self.info().currency
     }
 }
 
@@ -106,540 +566,6009 @@ struct Stock {
     exchange: Exchange,
     orders: Vec<Order>,
 
-    #[serde(skip_serializing, default)]
-    price: f64,
+    /// Stocks saved before currencies were tracked fall back to their exchange's currency
+    #[serde(default)]
+    currency: Option<Currency>,
+
+    #[serde(default)]
+    archived_on: Option<String>,
+
+    #[serde(default)]
+    splits: Vec<Split>,
+
+    /// Price to flag the stock at once it closes at or above it
+    #[serde(default)]
+    target_price: Option<f64>,
+
+    #[serde(default)]
+    dividends: Vec<Dividend>,
+
+    /// Latest close seen while online, shown by `list --offline`
+    #[serde(default)]
+    last_quote: Option<LastQuote>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct LastQuote {
+    close: f64,
+    close_yesterday: f64,
+    /// Trading date of the close
+    date: String,
+    /// When the close was fetched, as YYYY-MM-DD HH:MM
+    fetched_at: String,
+}
+
+impl LastQuote {
+    fn metrics(&self) -> StockMetrics {
+        let change = self.close - self.close_yesterday;
+        StockMetrics {
+            change,
+            change_percentage: 100.0 * fraction_of(change, self.close_yesterday),
+            close_today: self.close,
+            close_yesterday: self.close_yesterday,
+            date: self.date.clone(),
+            period: 1,
+        }
+    }
 }
 
 impl Stock {
+    fn dividend_income(&self) -> f64 {
+        self.dividends.iter().fold(0.0, |acc, x| acc + x.amount)
+    }
+
     fn calculate_order_metrics(&self) -> OrderMetrics {
+        self.calculate_order_metrics_by(CostMethod::Average)
+    }
+
+    /// Order metrics with the cost of sold shares worked out by `method`
+    fn calculate_order_metrics_by(&self, method: CostMethod) -> OrderMetrics {
         let total_spent = self.orders
             .iter()
-            .filter(|x| x.shares > 0)
-            .fold(0.0, |acc, x| acc + x.shares as f64 * x.share_price);
+            .filter(|x| x.shares > 0.0)
+            .fold(0.0, |acc, x| acc + x.shares * x.share_price + x.fee);
 
-        let total_sell = self.orders
+        let total_buy_shares = self.orders
             .iter()
-            .filter(|x| x.shares < 0)
-            .fold(0.0, |acc, x| acc + x.shares.abs() as f64 * x.share_price);
+            .filter(|x| x.shares > 0.0)
+            .fold(0.0, |acc, x| acc + x.shares);
+
+        let total_shares = self.orders.iter().fold(0.0, |acc, x| acc + x.shares);
 
-        let total_shares = self.orders.iter().fold(0, |acc, x| acc + x.shares);
+        let (book_cost, realized_gain) = match method {
+            CostMethod::Average => self.average_cost_basis(),
+            CostMethod::Fifo | CostMethod::Lifo => self.lot_cost_basis(method),
+        };
 
-        let average_price = total_spent / total_shares as f64;
+        // A closed position has nothing left to average over, so fall back to the average price
+        // paid across every buy
+        let average_cost = if total_shares > SHARE_EPSILON {
+            book_cost / total_shares
+        } else if total_buy_shares > SHARE_EPSILON {
+            total_spent / total_buy_shares
+        } else {
+            0.0
+        };
 
         return OrderMetrics {
             total_spent,
             total_shares,
-            average_price,
-            total_sell,
+            average_cost,
+            book_cost,
+            realized_gain,
         };
     }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Order {
-    shares: i32,
-    share_price: f64,
-}
-
-struct OrderMetrics {
-    total_spent: f64,
-    total_shares: i32,
-    average_price: f64,
-    total_sell: f64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct StockCollections {
-    portfolio: HashMap<String, Stock>,
-    watchlist: HashMap<String, Stock>,
-    archive: HashMap<String, Stock>,
-}
-
-struct StockMetrics {
-    change: f64,
-    change_percentage: f64,
-    close_today: f64,
-    close_yesterday: f64,
-}
 
-impl StockCollections {
-    fn new() -> StockCollections {
-        return StockCollections {
-            portfolio: HashMap::new(),
-            watchlist: HashMap::new(),
-            archive: HashMap::new(),
-        };
+    /// Average cost method: each sell removes shares at the running average cost, so the book
+    /// cost only carries the cost of the shares still held and selling never changes the
+    /// average cost of what is left. The realized gain of a sell is its proceeds, less its fee,
+    /// minus the average cost of the shares it removed. Returns the book cost and realized gain.
+    fn average_cost_basis(&self) -> (f64, f64) {
+        let mut held = 0.0;
+        let mut book_cost = 0.0;
+        let mut realized_gain = 0.0;
+        for order in self.orders_by_date() {
+            if order.shares > 0.0 {
+                held += order.shares;
+                book_cost += order.shares * order.share_price + order.fee;
+            } else if held > SHARE_EPSILON {
+                let sold = order.shares.abs().min(held);
+                let sold_cost = book_cost * sold / held;
+                realized_gain += sold * order.share_price - order.fee - sold_cost;
+                book_cost -= sold_cost;
+                held -= sold;
+            }
+        }
+        (book_cost, realized_gain)
     }
 
-    fn print_watch_list(&self) -> Result<(), StockoError> {
-        let mut table = Table::new();
+    /// Tax lot matching: every buy is a lot costing its price plus its fee, and each sell uses
+    /// up the oldest lots first for FIFO or the newest first for LIFO. The realized gain of a
+    /// sell is its proceeds, less its fee, minus the cost of the lots it used. Returns the cost
+    /// of the lots left over and the realized gain.
+    fn lot_cost_basis(&self, method: CostMethod) -> (f64, f64) {
+        // Shares left in each lot along with their cost per share
+        let mut lots: VecDeque<(f64, f64)> = VecDeque::new();
+        let mut realized_gain = 0.0;
 
-        table.add_row(Row::new(vec![Cell::new_with_alignment(
-            "Watch List",
-            3,
-            Alignment::Center,
-        )]));
+        for order in self.orders_by_date() {
+            if order.shares > 0.0 {
+                lots.push_back((order.shares, order.share_price + order.fee / order.shares));
+                continue;
+            }
 
-        table.add_row(Row::new(vec![
-            Cell::new("Symbol", 1),
-            Cell::new("Price", 1),
-            Cell::new("Change", 1),
-        ]));
+            let mut remaining = order.shares.abs();
+            let mut sold = 0.0;
+            let mut sold_cost = 0.0;
+            while remaining > SHARE_EPSILON {
+                let lot = match method {
+                    CostMethod::Lifo => lots.back_mut(),
+                    _ => lots.front_mut(),
+                };
+                let lot = match lot {
+                    Some(lot) => lot,
+                    None => break,
+                };
 
-        for stock in self.watchlist.values() {
-            let time_series = fetch_symbol_time_series(&stock.symbol)?;
-            let metrics = calculate_stock_metrics(time_series);
+                let taken = remaining.min(lot.0);
+                lot.0 -= taken;
+                sold_cost += taken * lot.1;
+                sold += taken;
+                remaining -= taken;
 
-            let change = generate_change_string(&metrics);
+                if lot.0 <= SHARE_EPSILON {
+                    match method {
+                        CostMethod::Lifo => lots.pop_back(),
+                        _ => lots.pop_front(),
+                    };
+                }
+            }
 
-            let row = Row::new(vec![
-                Cell::new(stock.symbol.clone(), 1),
-                Cell::new(metrics.close_today, 1),
-                Cell::new(change, 1),
-            ]);
-            table.add_row(row);
+            if sold > SHARE_EPSILON {
+                realized_gain += sold * order.share_price - order.fee - sold_cost;
+            }
         }
 
-        println!("{}", table.as_string());
+        let book_cost = lots
+            .iter()
+            .fold(0.0, |acc, &(shares, cost)| acc + shares * cost);
+        (book_cost, realized_gain)
+    }
 
-        Ok(())
+    fn currency(&self) -> Currency {
+        self.currency.unwrap_or_else(|| self.exchange.currency())
     }
 
-    fn print_portfolio(&self) -> Result<(), StockoError> {
-        let mut table = Table::new();
+    /// Orders sorted oldest first, undated orders come before any dated ones
+    fn orders_by_date(&self) -> Vec<&Order> {
+        let mut orders: Vec<&Order> = self.orders.iter().collect();
+        orders.sort_by(|a, b| a.date.cmp(&b.date));
+        orders
+    }
 
-        table.add_row(Row::new(vec![Cell::new_with_alignment(
-            "Portfolio",
-            6,
-            Alignment::Center,
-        )]));
+    /// Where the `index`th order listed by date is in `orders`, which keeps them as recorded
+    fn order_position(&self, index: usize) -> Option<usize> {
+        let mut positions: Vec<usize> = (0..self.orders.len()).collect();
+        positions.sort_by(|&a, &b| self.orders[a].date.cmp(&self.orders[b].date));
+        positions.get(index).cloned()
+    }
 
-        table.add_row(Row::new(vec![
-            Cell::new("Symbol", 1),
-            Cell::new("Price", 1),
-            Cell::new("Change", 1),
-            Cell::new("Shares", 1),
-            Cell::new("Book Cost", 1),
-            Cell::new("Total Gain", 1),
-        ]));
+    /// Rescales every order by `num`:`den`, keeping the cost of each order unchanged. When the
+    /// position no longer comes out to a whole number of shares it is rounded according to
+    /// `rounding`, spreading the difference across every order so the cost basis is the same
+    /// before and after the split. A position which would round down to nothing is kept as is.
+    fn apply_split(&mut self, num: u32, den: u32, rounding: Rounding) {
+        let ratio = f64::from(num) / f64::from(den);
+        let exact = self.calculate_order_metrics().total_shares * ratio;
 
-        for stock in self.portfolio.values() {
-            let time_series = fetch_symbol_time_series(&stock.symbol)?;
-            let order_metrics = stock.calculate_order_metrics();
-            let metrics = calculate_stock_metrics(time_series);
-            let change = generate_change_string(&metrics);
-
-            let overall_gain =
-                (metrics.close_today - order_metrics.average_price) / order_metrics.average_price;
-
-            let formatted_gain = if overall_gain >= 0.0 {
-                Green
-                    .paint(format!(
-                        "+{:.2} (+{:.2}%)",
-                        order_metrics.total_spent * overall_gain,
-                        overall_gain * 100.0
-                    ))
-                    .to_string()
-            } else {
-                Red.paint(format!(
-                    "{:.2} ({:.2}%)",
-                    order_metrics.total_spent * (1.0 + overall_gain) - order_metrics.total_spent,
-                    overall_gain * 100.0
-                )).to_string()
-            };
+        let target = match rounding {
+            Rounding::Floor => (exact + SHARE_EPSILON).floor(),
+            Rounding::Nearest => exact.round(),
+            Rounding::KeepFractional => exact,
+        };
+        let target = if target > 0.0 { target } else { exact };
 
-            let row = Row::new(vec![
-                Cell::new(stock.symbol.clone(), 1),
-                Cell::new(metrics.close_today, 1),
-                Cell::new(change, 1),
-                Cell::new(order_metrics.total_shares, 1),
-                Cell::new(
-                    order_metrics.total_shares as f64 * order_metrics.average_price,
-                    1,
-                ),
-                Cell::new(formatted_gain, 1),
-            ]);
-            table.add_row(row);
+        let effective_ratio = if exact > 0.0 {
+            ratio * target / exact
+        } else {
+            ratio
+        };
+
+        for order in &mut self.orders {
+            order.shares *= effective_ratio;
+            order.share_price /= effective_ratio;
         }
 
-        println!("{}", table.as_string());
+        let cash_in_lieu_shares = if exact - target > SHARE_EPSILON {
+            exact - target
+        } else {
+            0.0
+        };
 
-        Ok(())
+        self.splits.push(Split {
+            numerator: num,
+            denominator: den,
+            date: today(),
+            rounding,
+            cash_in_lieu_shares,
+        });
     }
+}
 
-    fn print_archive(&self) -> Result<(), StockoError> {
-        let mut table = Table::new();
+/// Cash paid out by a stock, the total received rather than the amount per share
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Dividend {
+    amount: f64,
+    date: String,
+}
 
-        table.add_row(Row::new(vec![Cell::new_with_alignment(
-            "Archive",
-            3,
-            Alignment::Center,
-        )]));
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Split {
+    numerator: u32,
+    denominator: u32,
+    date: String,
 
-        table.add_row(Row::new(vec![
-            Cell::new("Symbol", 1),
-            Cell::new("Orders", 1),
-            Cell::new("Gain", 1),
-        ]));
+    #[serde(default)]
+    rounding: Rounding,
 
-        let mut total_spent = 0.0;
-        let mut total_sell = 0.0;
+    /// Fractional shares paid out in cash rather than kept after rounding down
+    #[serde(default)]
+    cash_in_lieu_shares: f64,
+}
 
-        for stock in self.archive.values() {
-            let order_metrics = stock.calculate_order_metrics();
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+enum Rounding {
+    #[default]
+    Floor,
+    Nearest,
+    KeepFractional,
+}
 
-            let gain_percentage =
-                (order_metrics.total_sell - order_metrics.total_spent) / order_metrics.total_spent;
-            let overall_gain = order_metrics.total_sell - order_metrics.total_spent;
+impl Rounding {
+    fn from_name(name: Option<&str>) -> Rounding {
+        match name {
+            Some("nearest") => Rounding::Nearest,
+            Some("keep-fractional") => Rounding::KeepFractional,
+            _ => Rounding::Floor,
+        }
+    }
+}
 
-            total_spent += order_metrics.total_spent;
-            total_sell += order_metrics.total_sell;
-
-            let formatted_gain = generate_gain_string(overall_gain, gain_percentage);
+/// How the cost of sold shares is worked out
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CostMethod {
+    Average,
+    Fifo,
+    Lifo,
+}
 
-            let mut orders = String::new();
+impl CostMethod {
+    fn from_name(name: Option<&str>) -> CostMethod {
+        match name {
+            Some("fifo") => CostMethod::Fifo,
+            Some("lifo") => CostMethod::Lifo,
+            _ => CostMethod::Average,
+        }
+    }
+}
 
-            for order in &stock.orders {
-                orders += &*format!("{} @ {}\n", order.shares, order.share_price);
-            }
-            orders.pop();
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Order {
+    shares: f64,
+    share_price: f64,
+    /// Day the trade happened, orders recorded before dates were tracked have none
+    #[serde(default)]
+    date: Option<String>,
+    /// Commission paid on the trade, added to the cost of buys and taken from sell proceeds
+    #[serde(default)]
+    fee: f64,
+}
 
-            let row = Row::new(vec![
-                Cell::new(stock.symbol.clone(), 1),
-                Cell::new(orders, 1),
-                Cell::new(formatted_gain, 1),
-            ]);
-            table.add_row(row);
+impl Order {
+    /// One line description of the order, with its amounts at the `--precision` of `render`
+    /// and the shares as they were recorded
+    fn describe(&self, render: RenderOptions) -> String {
+        let mut description = String::new();
+        if let Some(ref date) = self.date {
+            description += &*format!("{}  ", date);
+        }
+        description += &*format!(
+            "{} @ {}",
+            generate_shares_string(self.shares),
+            render.number(self.share_price)
+        );
+        if self.fee > 0.0 {
+            description += &*format!(" + {} fee", render.number(self.fee));
         }
+        description
+    }
+}
 
-        let total_gain_percentage = (total_sell - total_spent) / total_spent;
-        let total_gain = total_sell - total_spent;
+struct OrderMetrics {
+    total_spent: f64,
+    total_shares: f64,
+    average_cost: f64,
+    book_cost: f64,
+    realized_gain: f64,
+}
 
-        let formatted_total_gain = generate_gain_string(total_gain, total_gain_percentage);
+impl OrderMetrics {
+    /// Gain on the shares still held if they were sold at `price`, as the amount and the
+    /// fraction of the book cost
+    fn unrealized_gain(&self, price: f64) -> (f64, f64) {
+        let fraction = fraction_of(price - self.average_cost, self.average_cost);
+        (self.book_cost * fraction, fraction)
+    }
 
-        table.add_row(Row::new(vec![
-            Cell::new("Total Gain", 2),
-            Cell::new(formatted_total_gain, 1),
-        ]));
+    /// Gain across every order of a closed position, as the amount and the fraction of the
+    /// total spent
+    fn closed_gain(&self) -> (f64, f64) {
+        (
+            self.realized_gain,
+            fraction_of(self.realized_gain, self.total_spent),
+        )
+    }
+}
 
-        println!("{}", table.as_string());
+#[derive(Serialize, Default)]
+struct Report {
+    portfolio: Vec<ReportEntry>,
+    watchlist: Vec<ReportEntry>,
+    archive: Vec<ReportEntry>,
+}
 
-        Ok(())
-    }
+#[derive(Serialize, Default)]
+struct ReportEntry {
+    symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    close_today: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    as_of: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change_percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_shares: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    book_cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_gain: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    realized_gain: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    income: Option<f64>,
 }
 
-fn main() -> Result<(), StockoError> {
-    let matches = App::new("managed-alias")
-        .version("1.0")
-        .author("Ryan Bluth")
-        .subcommand(
-            SubCommand::with_name("list")
-                .alias("l")
-                .about("Displays all stocks in portfolio"),
-        )
-        .subcommand(
-            SubCommand::with_name("watch")
-                .alias("w")
-                .about("Displays all stocks in portfolio")
-                .arg(
-                    Arg::with_name("exchange")
-                        .help("Exchange Symbol")
-                        .index(2)
-                        .required(false),
-                )
-                .arg(
-                    Arg::with_name("symbol")
-                        .help("Stock Symbol")
-                        .index(1)
-                        .required(true),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("buy")
-                .alias("b")
-                .about("Add shares to your portfolio")
-                .arg(
-                    Arg::with_name("exchange")
-                        .short("e")
-                        .help("Exchange Symbol")
-                        .takes_value(true)
-                        .required(false),
-                )
-                .arg(
-                    Arg::with_name("shares")
-                        .short("s")
-                        .help("Number of shares")
-                        .takes_value(true)
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("share_price")
-                        .short("p")
-                        .help("Share Price")
-                        .takes_value(true)
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("symbol")
-                        .help("Stock Symbol")
-                        .index(1)
-                        .required(true),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("sell")
-                .alias("s")
-                .about("Remove shares to your portfolio")
-                .arg(
-                    Arg::with_name("exchange")
-                        .short("e")
-                        .help("Exchange Symbol")
-                        .takes_value(true)
-                        .required(false),
-                )
-                .arg(
-                    Arg::with_name("shares")
-                        .short("s")
-                        .help("Number of shares")
-                        .takes_value(true)
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("share_price")
-                        .short("p")
-                        .help("Share Price")
-                        .takes_value(true)
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("symbol")
-                        .help("Stock Symbol")
-                        .index(1)
-                        .required(true),
-                ),
-        )
-        .get_matches();
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Config {
+    #[serde(default)]
+    api_key: Option<String>,
 
-    if matches.subcommand_matches("list").is_some() {
-        list()?;
-    } else if let Some(sub_matches) = matches.subcommand_matches("watch") {
-        let mut symbol = String::from(sub_matches.value_of("symbol").unwrap());
-        let exchange_value = sub_matches.value_of("exchange");
-        if let Some(exchange_symbol) = sub_matches.value_of("exchange") {
-            let suffix = suffix_for_exchange_symbol(exchange_symbol)?;
-            symbol.push_str(suffix);
-        }
-        watch(symbol, exchange_value)?;
-    } else if matches.subcommand_matches("buy").is_some()
-        || matches.subcommand_matches("sell").is_some()
-    {
-        let sub_matches = matches
-            .subcommand_matches("buy")
-            .unwrap_or_else(|| matches.subcommand_matches("sell").unwrap());
-        let mut symbol = String::from(sub_matches.value_of("symbol").unwrap());
-        let exchange_value = sub_matches.value_of("exchange");
-        if let Some(exchange_symbol) = sub_matches.value_of("exchange") {
-            let suffix = suffix_for_exchange_symbol(exchange_symbol)?;
-            symbol.push_str(suffix);
-        }
-        let mut shares = value_t!(sub_matches, "shares", i32).unwrap();
-        let price = value_t!(sub_matches, "share_price", f64).unwrap();
-        if matches.subcommand_matches("sell").is_some() {
-            shares *= -1;
+    /// Fixed exchange rates keyed like "USD/CAD", used instead of fetching the rate
+    #[serde(default)]
+    fx_rates: HashMap<String, f64>,
+}
+
+/// Everything in the data file, one set of collections per account
+#[derive(Debug, Serialize, Deserialize)]
+struct DataFile {
+    /// Layout of the file, older layouts are upgraded by `migrate` when loaded
+    version: u32,
+    accounts: HashMap<String, StockCollections>,
+}
+
+impl Default for DataFile {
+    fn default() -> Self {
+        DataFile {
+            version: DATA_VERSION,
+            accounts: HashMap::new(),
         }
-        process_order(symbol.to_uppercase(), exchange_value, shares, price)?;
     }
-    Ok(())
 }
 
-fn watch(symbol: String, exchange_symbol: Option<&str>) -> Result<(), StockoError> {
-    let mut collections = load_data()?;
-    // Run a fetch to make sure things are working
-    fetch_symbol_time_series(symbol.as_str())?;
-    let stock = Stock {
-        exchange: Exchange::from_symbol(exchange_symbol)?,
-        symbol: symbol.clone().to_uppercase(),
-        orders: Vec::new(),
-        ..Default::default()
-    };
+#[derive(Serialize)]
+struct AccountSummary<'a> {
+    name: &'a str,
+    portfolio: usize,
+    watchlist: usize,
+    archive: usize,
+}
 
-    collections
-        .watchlist
-        .insert(symbol.clone().to_uppercase(), stock);
-    save_data(collections)?;
-    Ok(())
+#[derive(Debug, Serialize, Deserialize)]
+struct StockCollections {
+    portfolio: HashMap<String, Stock>,
+    watchlist: HashMap<String, Stock>,
+    archive: HashMap<String, Stock>,
 }
 
-fn process_order(
-    symbol: String,
-    exchange_symbol: Option<&str>,
-    shares: i32,
-    price: f64,
-) -> Result<(), StockoError> {
-    let mut collection = load_data()?;
-    if !collection.portfolio.contains_key(&symbol) {
-        println!("{:?}", collection.portfolio);
-        if shares > 0 {
-            collection.portfolio.insert(
-                symbol.clone().to_uppercase(),
-                Stock {
-                    symbol: symbol.clone().to_uppercase(),
-                    exchange: Exchange::from_symbol(exchange_symbol)?,
-                    orders: Vec::new(),
-                    ..Default::default()
-                },
-            );
-        } else {
-            return Err(StockoError::InvalidShareQuantity {
-                symbol: symbol,
-                shares: shares.abs() as u32,
-            });
+/// A stock along with the figures shown for it in a table
+struct Holding<'a> {
+    stock: &'a Stock,
+    metrics: StockMetrics,
+    /// Only available for stocks with orders
+    order_metrics: Option<OrderMetrics>,
+    closes: Vec<f64>,
+}
+
+impl<'a> Holding<'a> {
+    fn value(&self) -> f64 {
+        match self.order_metrics {
+            Some(ref order_metrics) => self.metrics.close_today * order_metrics.total_shares,
+            None => 0.0,
         }
     }
 
-    let mut stock = collection.portfolio.get(&symbol).unwrap().clone();
-
-    let total_shares = stock.calculate_order_metrics().total_shares;
+    /// The target price if the stock has closed at or above it
+    fn target_reached(&self) -> Option<f64> {
+        self.stock
+            .target_price
+            .filter(|&target| self.metrics.close_today >= target)
+    }
 
-    println!("{}", total_shares);
+    /// Symbol cell, marked with a `*` when the target price has been reached
+    fn symbol_cell(&self, render: RenderOptions) -> String {
+        match self.target_reached() {
+            Some(_) => render.bold(format!("*{}", self.stock.symbol)),
+            None => self.stock.symbol.clone(),
+        }
+    }
 
-    if shares < 0 && total_shares < shares.abs() {
-        return Err(StockoError::InvalidShareQuantity {
-            symbol: symbol,
-            shares: shares.abs() as u32,
-        });
+    fn gain(&self) -> f64 {
+        match self.order_metrics {
+            Some(ref order_metrics) => order_metrics.unrealized_gain(self.metrics.close_today).0,
+            None => 0.0,
+        }
     }
+}
 
-    let order = Order {
-        shares: shares,
-        share_price: price,
-    };
+/// Sums of the portfolio figures, converted into the base currency when there is one
+#[derive(Default)]
+struct PortfolioTotals {
+    value: f64,
+    book_cost: f64,
+    gain: f64,
+    day_change: f64,
+    realized: f64,
+    income: f64,
+}
 
-    stock.orders.push(order);
+impl PortfolioTotals {
+    fn new(holdings: &[Holding], conversion: Option<&Conversion>) -> PortfolioTotals {
+        PortfolioTotals::sum(holdings.iter(), conversion)
+    }
 
-    if shares < 0 && total_shares == shares.abs() {
-        collection.portfolio.remove(&symbol);
-        collection.archive.insert(symbol, stock);
-    } else {
-        collection.portfolio.insert(symbol, stock);
+    fn sum<'a, 'b: 'a, I>(holdings: I, conversion: Option<&Conversion>) -> PortfolioTotals
+    where
+        I: Iterator<Item = &'a Holding<'b>>,
+    {
+        let mut totals = PortfolioTotals::default();
+        for holding in holdings {
+            let metrics = &holding.metrics;
+            let order_metrics = match holding.order_metrics {
+                Some(ref order_metrics) => order_metrics,
+                None => continue,
+            };
+            let currency = holding.stock.currency();
+            let convert = |amount: f64| match conversion {
+                Some(conversion) => conversion.convert(amount, currency),
+                None => amount,
+            };
+
+            totals.value += convert(metrics.close_today * order_metrics.total_shares);
+            totals.book_cost += convert(order_metrics.book_cost);
+            totals.gain += convert(order_metrics.unrealized_gain(metrics.close_today).0);
+            totals.day_change += convert(metrics.change * order_metrics.total_shares);
+            totals.realized += convert(order_metrics.realized_gain);
+            totals.income += convert(holding.stock.dividend_income());
+        }
+        totals
     }
 
-    save_data(collection)?;
+    /// Capital gains, realized or not, plus dividends
+    fn total_return(&self) -> f64 {
+        self.gain + self.realized + self.income
+    }
 
-    Ok(())
-}
+    fn gain_percentage(&self) -> f64 {
+        if self.book_cost > 0.0 {
+            self.gain / self.book_cost
+        } else {
+            0.0
+        }
+    }
 
-fn fetch_symbol_time_series(symbol: &str) -> Result<TimeSeries, StockoError> {
-    let client = alphavantage::Client::new("BUN9HP4GJXX524JS");
-    let time_series = mapStockoErr!(
-        StockoError::AlphaVantageError,
-        client.get_time_series_daily(symbol)
-    )?;
+    fn day_change_percentage(&self) -> f64 {
+        let value_yesterday = self.value - self.day_change;
+        if value_yesterday > 0.0 {
+            self.day_change / value_yesterday
+        } else {
+            0.0
+        }
+    }
 
-    return Ok(time_series);
+    /// A row of the portfolio table lined up under the columns it adds up
+    fn cells(
+        &self,
+        label: String,
+        weight: Option<f64>,
+        benchmark: Option<&StockMetrics>,
+        render: RenderOptions,
+    ) -> Vec<Cell<'static>> {
+        let mut cells = vec![
+            Cell::new(label, 2),
+            Cell::new(render.number(self.value), 1),
+            Cell::new(
+                generate_gain_string(self.day_change, self.day_change_percentage(), render),
+                1,
+            ),
+            Cell::new("", 2),
+            Cell::new(render.number(self.book_cost), 1),
+            Cell::new(
+                generate_gain_string(self.gain, self.gain_percentage(), render),
+                1,
+            ),
+            Cell::new(generate_amount_string(self.realized, render), 1),
+            Cell::new(render.number(self.income), 1),
+            Cell::new(generate_amount_string(self.total_return(), render), 1),
+            Cell::new(generate_weight_string(weight), 1),
+        ];
+        if let Some(benchmark) = benchmark {
+            let difference = self.day_change_percentage() * 100.0 - benchmark.change_percentage;
+            cells.push(Cell::new(generate_benchmark_string(difference, render), 1));
+        }
+        cells
+    }
 }
 
-fn list() -> Result<(), StockoError> {
-    let collection = load_data()?;
-    collection.print_portfolio()?;
-    collection.print_watch_list()?;
-    collection.print_archive()?;
-    Ok(())
+/// Row for a stock whose price couldn't be fetched, with a note spanning `columns` columns
+/// after the symbol
+fn unavailable_row(stock: &Stock, columns: usize) -> Row<'static> {
+    Row::new(vec![
+        Cell::new(stock.symbol.clone(), 1),
+        Cell::new("unavailable", columns),
+    ])
 }
 
-fn save_data(collections: StockCollections) -> Result<(), StockoError> {
-    let mut file = mapStockoErr!(
-        StockoError::SaveDataError,
-        OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .read(true)
-            .open(get_data_file_path())
-    )?;
+/// A line for every holding that reached its target price
+fn reached_targets(holdings: &[Holding]) -> String {
+    let mut s = String::new();
+    for holding in holdings {
+        if let Some(target) = holding.target_reached() {
+            s += &*format!(
+                "{} reached target {:.2} (now {:.2})\n",
+                holding.stock.symbol, target, holding.metrics.close_today
+            );
+        }
+    }
+    s
+}
 
-    let json = mapStockoErr!(StockoError::SaveDataError, serde_json::to_vec(&collections))?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortField {
+    Symbol,
+    Price,
+    Change,
+    Gain,
+    Value,
+}
 
-    return mapStockoErr!(StockoError::SaveDataError, file.write_all(&*json));
+/// Which end of the day's changes `movers` shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MoverDirection {
+    Top,
+    Worst,
 }
 
-fn load_data() -> Result<StockCollections, StockoError> {
-    let path = get_data_file_path();
+impl MoverDirection {
+    fn from_name(name: Option<&str>) -> MoverDirection {
+        match name {
+            Some("worst") => MoverDirection::Worst,
+            _ => MoverDirection::Top,
+        }
+    }
+}
 
-    if !path.exists() {
-        return Ok(StockCollections::new());
+impl SortField {
+    fn from_name(name: Option<&str>) -> SortField {
+        match name {
+            Some("price") => SortField::Price,
+            Some("change") => SortField::Change,
+            Some("gain") => SortField::Gain,
+            Some("value") => SortField::Value,
+            _ => SortField::Symbol,
+        }
     }
+}
 
-    let mut file = mapStockoErr!(StockoError::ReadDataError, File::open(path))?;
+#[derive(Debug, Clone, Copy)]
+struct SortOrder {
+    field: SortField,
+    descending: bool,
+}
 
-    let mut buf = String::new();
-    file.read_to_string(&mut buf).unwrap();
+impl SortOrder {
+    /// Sorts by the chosen field with ties broken by symbol. Watch list stocks have no gain or
+    /// value, so those fields leave them in symbol order.
+    fn apply(&self, holdings: &mut [Holding]) {
+        let field = self.field;
+        let key = |holding: &Holding| match field {
+            SortField::Symbol => 0.0,
+            SortField::Price => holding.metrics.close_today,
+            SortField::Change => holding.metrics.change_percentage,
+            SortField::Gain => holding.gain(),
+            SortField::Value => holding.value(),
+        };
 
-    return mapStockoErr!(
-        StockoError::ReadDataError,
-        serde_json::from_str::<StockCollections>(buf.as_str())
-    );
+        holdings.sort_by(|a, b| {
+            let ordering = key(a)
+                .partial_cmp(&key(b))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.stock.symbol.cmp(&b.stock.symbol));
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
 }
 
-fn get_data_file_path() -> PathBuf {
-    let mut exe_path = std::env::current_exe().unwrap();
-    exe_path.pop();
-    exe_path.push("stocko_data.json");
-    return exe_path;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Collection {
+    Portfolio,
+    Watchlist,
+    Archive,
+    All,
 }
 
-fn suffix_for_exchange_symbol(exchange_symbol: &str) -> Result<&'static str, StockoError> {
-    match exchange_symbol.to_lowercase().as_ref() {
-        "tsx" => Ok(".TO"),
-        "tsxv" => Ok(".V"),
-        "nsye" => Ok(""),
-        _ => Err(StockoError::InvalidExchange),
+impl Collection {
+    fn from_name(name: &str) -> Result<Collection, StockoError> {
+        match name.to_lowercase().as_ref() {
+            "portfolio" => Ok(Collection::Portfolio),
+            "watchlist" => Ok(Collection::Watchlist),
+            "archive" => Ok(Collection::Archive),
+            "all" => Ok(Collection::All),
+            _ => Err(StockoError::InvalidCollection(String::from(name))),
+        }
     }
-}
 
-fn calculate_stock_metrics(time_series: TimeSeries) -> StockMetrics {
-    let entries = time_series.entries();
-    let num_entries = entries.len();
-    let mut entry_iter = entries.into_iter();
+    /// Whether `section` is part of this collection, `All` includes every section
+    fn includes(self, section: Collection) -> bool {
+        match self {
+            Collection::All => true,
+            _ => self == section,
+        }
+    }
 
-    let (_date_yesterday, entry_yesterday) = entry_iter.nth(num_entries - 2).unwrap();
-    let (_date_today, entry_today) = entry_iter.last().unwrap();
+    fn name(&self) -> &'static str {
+        match *self {
+            Collection::Portfolio => "portfolio",
+            Collection::Watchlist => "watchlist",
+            Collection::Archive => "archive",
+            Collection::All => "all collections",
+        }
+    }
+}
 
-    let change_value = entry_today.close - entry_yesterday.close;
-    let change_percentage =
-        100.0 * (entry_today.close - entry_yesterday.close) / entry_yesterday.close;
+/// Source of the price history shown for each stock, along with the symbol search and
+/// exchange rates used when adding stocks and converting totals
+trait Fetcher: Send + Sync {
+    fn fetch(&self, symbol: &str) -> Result<TimeSeries, StockoError>;
+    fn search(&self, keywords: &str) -> Result<Vec<SearchMatch>, StockoError>;
+    /// Rate for converting `from` into `to`
+    fn fx_rate(&self, from: Currency, to: Currency) -> Result<f64, StockoError>;
+}
 
-    return StockMetrics {
-        change_percentage: change_percentage,
-        change: change_value,
-        close_today: entry_today.close,
-        close_yesterday: entry_yesterday.close,
-    };
+/// Fetches from AlphaVantage, or whichever server STOCKO_API_URL points at
+struct AlphaVantageFetcher {
+    options: FetchOptions,
 }
 
-fn generate_change_string(metrics: &StockMetrics) -> String {
-    return if metrics.change >= 0.0 {
-        Green
-            .paint(format!(
-                "+{:.2} (+{:.2}%)",
-                metrics.change, metrics.change_percentage
-            ))
-            .to_string()
-    } else {
-        Red.paint(format!(
-            "{:.2} ({:.2}%)",
-            metrics.change, metrics.change_percentage
-        )).to_string()
-    };
+impl Fetcher for AlphaVantageFetcher {
+    fn fetch(&self, symbol: &str) -> Result<TimeSeries, StockoError> {
+        fetch_symbol_time_series(symbol, self.options)
+    }
+
+    fn search(&self, keywords: &str) -> Result<Vec<SearchMatch>, StockoError> {
+        retry_with_backoff(self.options.retries, RETRY_BASE_DELAY, || {
+            let body =
+                request_alphavantage(&[("function", "SYMBOL_SEARCH"), ("keywords", keywords)])?;
+            parse_symbol_search_response(&body)
+        })
+    }
+
+    fn fx_rate(&self, from: Currency, to: Currency) -> Result<f64, StockoError> {
+        let pair = format!("{}/{}", from.code(), to.code());
+        retry_with_backoff(self.options.retries, RETRY_BASE_DELAY, || {
+            let body = request_alphavantage(&[
+                ("function", "CURRENCY_EXCHANGE_RATE"),
+                ("from_currency", from.code()),
+                ("to_currency", to.code()),
+            ])?;
+            parse_fx_rate_response(&pair, &body)
+        })
+    }
 }
 
-fn generate_gain_string(gain: f64, gain_percentage: f64) -> String {
-    return if gain >= 0.0 {
-        Green
-            .paint(format!("+{:.2} (+{:.2}%)", gain, gain_percentage * 100.0))
-            .to_string()
-    } else {
-        Red.paint(format!("{:.2} ({:.2}%)", gain, gain_percentage * 100.0))
-            .to_string()
-    };
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Interval {
+    fn from_name(name: Option<&str>) -> Interval {
+        match name {
+            Some("weekly") => Interval::Weekly,
+            Some("monthly") => Interval::Monthly,
+            _ => Interval::Daily,
+        }
+    }
+
+    /// AlphaVantage function returning a time series at this interval
+    fn function(self) -> &'static str {
+        match self {
+            Interval::Daily => "TIME_SERIES_DAILY",
+            Interval::Weekly => "TIME_SERIES_WEEKLY",
+            Interval::Monthly => "TIME_SERIES_MONTHLY",
+        }
+    }
+
+    /// Key the entries are found under in the response to `function`
+    fn series_key(self) -> &'static str {
+        match self {
+            Interval::Daily => "Time Series (Daily)",
+            Interval::Weekly => "Weekly Time Series",
+            Interval::Monthly => "Monthly Time Series",
+        }
+    }
+
+    /// Short unit for a number of entries at this interval, as in 5d
+    fn unit(self) -> &'static str {
+        match self {
+            Interval::Daily => "d",
+            Interval::Weekly => "w",
+            Interval::Monthly => "mo",
+        }
+    }
+}
+
+/// Settings shared by every request made while running a command
+#[derive(Debug, Clone, Copy)]
+struct FetchOptions {
+    retries: u32,
+    interval: Interval,
+    /// Most requests made at the same time when fetching several symbols
+    concurrency: usize,
+}
+
+/// Time series fetched while running a single command, so each symbol is only requested once
+struct QuoteCache<'a> {
+    fetcher: &'a dyn Fetcher,
+    /// Each time series along with when it was fetched
+    series: HashMap<String, (TimeSeries, DateTime<Local>)>,
+    /// Uses the quotes stored with each stock instead of fetching
+    offline: bool,
+    /// Number of entries back the change is measured from. Stored quotes only cover one
+    lookback: usize,
+    /// Interval of the fetched entries, which the lookback is labelled with
+    interval: Interval,
+    /// Why each symbol that failed in the last prefetch failed, so it isn't requested again
+    failed: HashMap<String, StockoError>,
+}
+
+impl<'a> QuoteCache<'a> {
+    fn new(fetcher: &'a dyn Fetcher) -> QuoteCache<'a> {
+        QuoteCache {
+            fetcher,
+            series: HashMap::new(),
+            offline: false,
+            lookback: 1,
+            interval: Interval::Daily,
+            failed: HashMap::new(),
+        }
+    }
+
+    fn metrics(&mut self, stock: &Stock) -> Result<StockMetrics, StockoError> {
+        if self.offline {
+            return match stock.last_quote {
+                Some(ref last_quote) => Ok(last_quote.metrics()),
+                None => Err(StockoError::NoStoredPrice(stock.symbol.clone())),
+            };
+        }
+        let lookback = self.lookback;
+        calculate_stock_metrics(&stock.symbol, self.get(&stock.symbol)?, lookback)
+    }
+
+    /// The recent closes for the trend line, which are not stored so there are none offline
+    fn recent_closes(&mut self, stock: &Stock, count: usize) -> Result<Vec<f64>, StockoError> {
+        if self.offline {
+            return Ok(Vec::new());
+        }
+        Ok(recent_closes(self.get(&stock.symbol)?, count))
+    }
+
+    fn get(&mut self, symbol: &str) -> Result<&TimeSeries, StockoError> {
+        if let Some(e) = self.failed.get(symbol) {
+            return Err(e.clone());
+        }
+        if !self.series.contains_key(symbol) {
+            let time_series = self.fetcher.fetch(symbol)?;
+            self.series
+                .insert(String::from(symbol), (time_series, Local::now()));
+        }
+        Ok(&self.series[symbol].0)
+    }
+
+    /// Drops the prices fetched more than `max_age` ago so they are fetched again
+    fn expire(&mut self, max_age: Duration) {
+        let now = Local::now();
+        self.series.retain(|_, entry| {
+            now.signed_duration_since(entry.1)
+                .to_std()
+                .map_or(true, |age| age < max_age)
+        });
+    }
+
+    /// When the oldest of the cached prices was fetched
+    fn fetched_at(&self) -> Option<DateTime<Local>> {
+        self.series.values().map(|x| x.1).min()
+    }
+
+    /// Fetches every symbol which is not cached yet, making up to `concurrency` requests at a
+    /// time. When any of them fail the error for the first failed symbol is returned, and the
+    /// failed symbols keep returning their error until the next prefetch.
+    fn prefetch<'s, I>(&mut self, symbols: I, concurrency: usize) -> Result<(), StockoError>
+    where
+        I: IntoIterator<Item = &'s str>,
+    {
+        let mut pending: Vec<&str> = symbols
+            .into_iter()
+            .filter(|x| !self.series.contains_key(*x))
+            .collect();
+        pending.sort();
+        pending.dedup();
+
+        let fetcher = &self.fetcher;
+        let queue = Mutex::new(pending.into_iter());
+        let results = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..concurrency.max(1) {
+                scope.spawn(|| loop {
+                    let symbol = match queue.lock().unwrap().next() {
+                        Some(symbol) => symbol,
+                        None => break,
+                    };
+                    let result = fetcher.fetch(symbol).map(|x| (x, Local::now()));
+                    results.lock().unwrap().push((symbol, result));
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut first_error = None;
+        for (symbol, result) in results {
+            match result {
+                Ok(fetched) => {
+                    self.failed.remove(symbol);
+                    self.series.insert(String::from(symbol), fetched);
+                }
+                Err(e) => {
+                    if first_error.is_none() {
+                        first_error = Some(e.clone());
+                    }
+                    self.failed.insert(String::from(symbol), e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A listing returned by AlphaVantage's symbol search
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchMatch {
+    #[serde(rename(deserialize = "1. symbol"))]
+    symbol: String,
+    #[serde(rename(deserialize = "2. name"))]
+    name: String,
+    #[serde(rename(deserialize = "4. region"))]
+    region: String,
+    #[serde(rename(deserialize = "8. currency"))]
+    currency: String,
+}
+
+/// Exchange rates for converting amounts into a single base currency
+struct Conversion {
+    base: Currency,
+    rates: HashMap<Currency, f64>,
+}
+
+impl Conversion {
+    /// Looks up the rate from every currency in `currencies` to `base`
+    fn new(
+        base: Currency,
+        currencies: &[Currency],
+        fetcher: &dyn Fetcher,
+    ) -> Result<Conversion, StockoError> {
+        let mut rates = HashMap::new();
+        rates.insert(base, 1.0);
+        for &currency in currencies {
+            if let Entry::Vacant(entry) = rates.entry(currency) {
+                entry.insert(get_fx_rate(currency, base, fetcher)?);
+            }
+        }
+        Ok(Conversion { base, rates })
+    }
+
+    fn convert(&self, amount: f64, from: Currency) -> f64 {
+        amount * self.rates.get(&from).cloned().unwrap_or(1.0)
+    }
+}
+
+struct StockMetrics {
+    change: f64,
+    change_percentage: f64,
+    close_today: f64,
+    /// Close the change is measured from, `period` entries before the latest
+    close_yesterday: f64,
+    /// Trading date of the latest close
+    date: String,
+    /// Number of entries the change covers, which is less than asked for on a short series
+    period: usize,
+}
+
+impl StockCollections {
+    fn new() -> StockCollections {
+        return StockCollections {
+            portfolio: HashMap::new(),
+            watchlist: HashMap::new(),
+            archive: HashMap::new(),
+        };
+    }
+
+    fn count(&self, target: Collection) -> usize {
+        match target {
+            Collection::Portfolio => self.portfolio.len(),
+            Collection::Watchlist => self.watchlist.len(),
+            Collection::Archive => self.archive.len(),
+            Collection::All => self.portfolio.len() + self.watchlist.len() + self.archive.len(),
+        }
+    }
+
+    /// Sorted symbols of every stock in `target`
+    fn symbols(&self, target: Collection) -> Vec<&String> {
+        let mut symbols = Vec::new();
+        if target.includes(Collection::Portfolio) {
+            symbols.extend(self.portfolio.keys());
+        }
+        if target.includes(Collection::Watchlist) {
+            symbols.extend(self.watchlist.keys());
+        }
+        if target.includes(Collection::Archive) {
+            symbols.extend(self.archive.keys());
+        }
+        symbols.sort();
+        symbols
+    }
+
+    /// Adds an order to a stock in the portfolio, adding the stock when it is bought for the
+    /// first time. Selling more shares than are held is rejected, and selling every share moves
+    /// the stock to the archive.
+    fn apply_order(
+        &mut self,
+        symbol: String,
+        exchange: Exchange,
+        order: Order,
+    ) -> Result<(), StockoError> {
+        let shares = order.shares;
+
+        let mut stock = match self.portfolio.remove(&symbol) {
+            Some(stock) => stock,
+            None if shares > 0.0 => Stock {
+                symbol: symbol.clone(),
+                currency: Some(exchange.currency()),
+                exchange,
+                orders: Vec::new(),
+                ..Default::default()
+            },
+            None => {
+                return Err(StockoError::InvalidShareQuantity {
+                    symbol,
+                    shares: shares.abs(),
+                })
+            }
+        };
+
+        let total_shares = stock.calculate_order_metrics().total_shares;
+
+        if shares < 0.0 && total_shares + SHARE_EPSILON < shares.abs() {
+            self.portfolio.insert(symbol.clone(), stock);
+            return Err(StockoError::InvalidShareQuantity {
+                symbol,
+                shares: shares.abs(),
+            });
+        }
+
+        let archived_on = order.date.clone().unwrap_or_else(today);
+        stock.orders.push(order);
+
+        if shares < 0.0 && (total_shares - shares.abs()).abs() < SHARE_EPSILON {
+            stock.archived_on = Some(archived_on);
+            self.archive.insert(symbol, stock);
+        } else {
+            self.portfolio.insert(symbol, stock);
+        }
+
+        Ok(())
+    }
+
+    /// Whether selling `shares` would leave no shares of `symbol`, moving it to the archive
+    fn closes_position(&self, symbol: &str, shares: f64) -> bool {
+        match self.portfolio.get(symbol) {
+            Some(stock) if shares < 0.0 => {
+                let total_shares = stock.calculate_order_metrics().total_shares;
+                (total_shares - shares.abs()).abs() < SHARE_EPSILON
+            }
+            _ => false,
+        }
+    }
+
+    fn clear(&mut self, target: Collection) -> usize {
+        let removed = self.count(target);
+        match target {
+            Collection::Portfolio => self.portfolio.clear(),
+            Collection::Watchlist => self.watchlist.clear(),
+            Collection::Archive => self.archive.clear(),
+            Collection::All => {
+                self.portfolio.clear();
+                self.watchlist.clear();
+                self.archive.clear();
+            }
+        }
+        removed
+    }
+
+    /// Stores the latest close of every fetched stock so it can be shown offline later
+    fn remember_quotes(&mut self, cache: &QuoteCache) {
+        let watchlist = self.watchlist.values_mut();
+        for stock in self.portfolio.values_mut().chain(watchlist) {
+            if let Some(&(ref time_series, fetched_at)) = cache.series.get(&stock.symbol) {
+                if let Ok(metrics) = calculate_stock_metrics(&stock.symbol, time_series, 1) {
+                    stock.last_quote = Some(LastQuote {
+                        close: metrics.close_today,
+                        close_yesterday: metrics.close_yesterday,
+                        date: metrics.date,
+                        fetched_at: fetched_at.format("%Y-%m-%d %H:%M").to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// When the oldest of the stored quotes was fetched
+    fn stored_quotes_fetched_at(&self) -> Option<&str> {
+        self.portfolio
+            .values()
+            .chain(self.watchlist.values())
+            .filter_map(|x| x.last_quote.as_ref())
+            .map(|x| x.fetched_at.as_str())
+            .min()
+    }
+
+    fn report(
+        &self,
+        cache: &mut QuoteCache,
+        only: Collection,
+        method: CostMethod,
+    ) -> Result<Report, StockoError> {
+        let mut report = Report::default();
+
+        if only.includes(Collection::Portfolio) {
+            for stock in self.portfolio.values() {
+                let order_metrics = stock.calculate_order_metrics_by(method);
+                let mut entry = ReportEntry {
+                    symbol: stock.symbol.clone(),
+                    currency: Some(stock.currency()),
+                    total_shares: Some(order_metrics.total_shares),
+                    book_cost: Some(order_metrics.book_cost),
+                    realized_gain: Some(order_metrics.realized_gain),
+                    income: Some(stock.dividend_income()),
+                    ..Default::default()
+                };
+
+                // A stock whose price couldn't be fetched is reported without one
+                if let Ok(metrics) = cache.metrics(stock) {
+                    let (gain, _) = order_metrics.unrealized_gain(metrics.close_today);
+                    entry.close_today = Some(metrics.close_today);
+                    entry.as_of = Some(metrics.date.clone());
+                    entry.change = Some(metrics.change);
+                    entry.change_percentage = Some(metrics.change_percentage);
+                    entry.total_gain = Some(gain);
+                }
+
+                report.portfolio.push(entry);
+            }
+        }
+
+        if only.includes(Collection::Watchlist) {
+            for stock in self.watchlist.values() {
+                let mut entry = ReportEntry {
+                    symbol: stock.symbol.clone(),
+                    currency: Some(stock.currency()),
+                    ..Default::default()
+                };
+
+                if let Ok(metrics) = cache.metrics(stock) {
+                    entry.close_today = Some(metrics.close_today);
+                    entry.as_of = Some(metrics.date.clone());
+                    entry.change = Some(metrics.change);
+                    entry.change_percentage = Some(metrics.change_percentage);
+                }
+
+                report.watchlist.push(entry);
+            }
+        }
+
+        if only.includes(Collection::Archive) {
+            for stock in self.archive.values() {
+                let (gain, _) = stock.calculate_order_metrics_by(method).closed_gain();
+
+                report.archive.push(ReportEntry {
+                    symbol: stock.symbol.clone(),
+                    total_gain: Some(gain),
+                    ..Default::default()
+                });
+            }
+        }
+
+        report.portfolio.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        report.watchlist.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        report.archive.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        Ok(report)
+    }
+
+    fn render_watch_list(
+        &self,
+        cache: &mut QuoteCache,
+        render: RenderOptions,
+        sort: SortOrder,
+    ) -> Result<String, StockoError> {
+        let mut table = Table::new();
+
+        table.add_row(Row::new(vec![Cell::new_with_alignment(
+            "Watch List",
+            4,
+            Alignment::Center,
+        )]));
+
+        table.add_row(Row::new(vec![
+            Cell::new("Symbol", 1),
+            Cell::new("Price", 1),
+            Cell::new(change_header(cache.lookback, cache.interval), 1),
+            Cell::new("Trend", 1),
+        ]));
+
+        let mut holdings = Vec::new();
+        let mut unavailable = Vec::new();
+        for stock in self.watchlist.values() {
+            let metrics = match cache.metrics(stock) {
+                Ok(metrics) => metrics,
+                Err(_) => {
+                    unavailable.push(stock);
+                    continue;
+                }
+            };
+            holdings.push(Holding {
+                stock,
+                metrics,
+                order_metrics: None,
+                closes: cache.recent_closes(stock, SPARKLINE_LENGTH)?,
+            });
+        }
+        sort.apply(&mut holdings);
+        unavailable.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        for holding in &holdings {
+            let metrics = &holding.metrics;
+            let change =
+                generate_period_change_string(metrics, cache.lookback, cache.interval, render);
+            let trend = generate_trend_string(&holding.closes, render);
+
+            let row = Row::new(vec![
+                Cell::new(holding.symbol_cell(render), 1),
+                Cell::new(generate_price_string(metrics, render), 1),
+                Cell::new(change, 1),
+                Cell::new(trend, 1),
+            ]);
+            table.add_row(row);
+        }
+
+        for stock in unavailable {
+            table.add_row(unavailable_row(stock, 3));
+        }
+
+        let mut s = format!("{}\n", render.table(table));
+        s += &*reached_targets(&holdings);
+
+        Ok(s)
+    }
+
+    /// The portfolio's holdings, along with the stocks left out because their price couldn't
+    /// be fetched, sorted by symbol
+    fn portfolio_holdings(
+        &self,
+        cache: &mut QuoteCache,
+        method: CostMethod,
+    ) -> (Vec<Holding<'_>>, Vec<&Stock>) {
+        let mut holdings = Vec::new();
+        let mut unavailable = Vec::new();
+        for stock in self.portfolio.values() {
+            match cache.metrics(stock) {
+                Ok(metrics) => holdings.push(Holding {
+                    stock,
+                    metrics,
+                    order_metrics: Some(stock.calculate_order_metrics_by(method)),
+                    closes: Vec::new(),
+                }),
+                Err(_) => unavailable.push(stock),
+            }
+        }
+        unavailable.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        (holdings, unavailable)
+    }
+
+    /// Renders only the portfolio totals, with the realized gain including closed positions
+    fn render_summary(
+        &self,
+        cache: &mut QuoteCache,
+        render: RenderOptions,
+        conversion: Option<&Conversion>,
+        method: CostMethod,
+    ) -> Result<String, StockoError> {
+        let (holdings, unavailable) = self.portfolio_holdings(cache, method);
+        let totals = PortfolioTotals::new(&holdings, conversion);
+        for stock in unavailable {
+            eprintln!("{} is left out as its price is unavailable", stock.symbol);
+        }
+
+        let mut realized = totals.realized;
+        let mut income = totals.income;
+        for stock in self.archive.values() {
+            let convert = |amount: f64| match conversion {
+                Some(conversion) => conversion.convert(amount, stock.currency()),
+                None => amount,
+            };
+            realized += convert(stock.calculate_order_metrics_by(method).realized_gain);
+            income += convert(stock.dividend_income());
+        }
+        let total_return = totals.gain + realized + income;
+
+        let mut s = String::new();
+        if let Some(conversion) = conversion {
+            s += &*format!("Currency      {}\n", conversion.base.code());
+        }
+        s += &*format!("Book cost     {}\n", render.number(totals.book_cost));
+        s += &*format!("Market value  {}\n", render.number(totals.value));
+        s += &*format!(
+            "Unrealized    {}\n",
+            generate_gain_string(totals.gain, totals.gain_percentage(), render)
+        );
+        s += &*format!(
+            "Realized      {}\n",
+            generate_amount_string(realized, render)
+        );
+        s += &*format!("Income        {}\n", render.number(income));
+        s += &*format!(
+            "Total return  {}\n",
+            generate_amount_string(total_return, render)
+        );
+
+        Ok(s)
+    }
+
+    /// Renders the portfolio with a totals row, which is converted into a single currency when a
+    /// `conversion` is given, preceded by a subtotal for each currency when there are several.
+    /// Several currencies without a conversion can't be added up or weighed against each
+    /// other, so only get their subtotals and no weights.
+    fn render_portfolio(
+        &self,
+        cache: &mut QuoteCache,
+        render: RenderOptions,
+        conversion: Option<&Conversion>,
+        sort: SortOrder,
+        benchmark: Option<&StockMetrics>,
+        method: CostMethod,
+    ) -> Result<String, StockoError> {
+        let (mut holdings, unavailable) = self.portfolio_holdings(cache, method);
+        sort.apply(&mut holdings);
+
+        // Weights are worked out against the whole portfolio, so everything is added up before
+        // any row is printed
+        let totals = PortfolioTotals::new(&holdings, conversion);
+
+        // Holdings in different currencies are also added up on their own, unconverted
+        let mut currencies: Vec<Currency> = holdings.iter().map(|x| x.stock.currency()).collect();
+        currencies.sort_by_key(|x| x.code());
+        currencies.dedup();
+        let mixed_currencies = currencies.len() > 1;
+        let currency_totals: Vec<(Currency, PortfolioTotals)> = currencies
+            .into_iter()
+            .map(|currency| {
+                let holdings = holdings.iter().filter(|x| x.stock.currency() == currency);
+                (currency, PortfolioTotals::sum(holdings, None))
+            })
+            .collect();
+
+        // Values in different currencies can only be compared once converted
+        let weight = |value: f64, currency: Currency| match conversion {
+            Some(conversion) => Some(fraction_of(
+                conversion.convert(value, currency),
+                totals.value,
+            )),
+            None if mixed_currencies => None,
+            None => Some(fraction_of(value, totals.value)),
+        };
+
+        let mut subtotals = Vec::new();
+        if mixed_currencies {
+            for &(currency, ref subtotal) in &currency_totals {
+                let subtotal_weight = weight(subtotal.value, currency);
+                subtotals.push((currency, subtotal, subtotal_weight));
+            }
+        }
+
+        let mut table = Table::new();
+
+        table.add_row(Row::new(vec![Cell::new_with_alignment(
+            "Portfolio",
+            if benchmark.is_some() { 13 } else { 12 },
+            Alignment::Center,
+        )]));
+
+        let mut header = vec![
+            Cell::new("Symbol", 1),
+            Cell::new("Currency", 1),
+            Cell::new("Price", 1),
+            Cell::new(change_header(cache.lookback, cache.interval), 1),
+            Cell::new("Shares", 1),
+            Cell::new("Avg Cost", 1),
+            Cell::new("Book Cost", 1),
+            Cell::new("Total Gain", 1),
+            Cell::new("Realized", 1),
+            Cell::new("Income", 1),
+            Cell::new("Total Return", 1),
+            Cell::new("Weight", 1),
+        ];
+        if benchmark.is_some() {
+            header.push(Cell::new("vs Bench", 1));
+        }
+        table.add_row(Row::new(header));
+
+        for holding in &holdings {
+            let stock = holding.stock;
+            let metrics = &holding.metrics;
+            let order_metrics = holding.order_metrics.as_ref().unwrap();
+            let change =
+                generate_period_change_string(metrics, cache.lookback, cache.interval, render);
+
+            let (gain, overall_gain) = order_metrics.unrealized_gain(metrics.close_today);
+            let formatted_gain = generate_gain_string(gain, overall_gain, render);
+            let realized = generate_amount_string(order_metrics.realized_gain, render);
+            let income = stock.dividend_income();
+            let total_return = gain + order_metrics.realized_gain + income;
+            let holding_weight = weight(holding.value(), stock.currency());
+
+            let mut cells = vec![
+                Cell::new(holding.symbol_cell(render), 1),
+                Cell::new(stock.currency().code(), 1),
+                Cell::new(generate_price_string(metrics, render), 1),
+                Cell::new(change, 1),
+                Cell::new(generate_shares_string(order_metrics.total_shares), 1),
+                Cell::new(render.number(order_metrics.average_cost), 1),
+                Cell::new(render.number(order_metrics.book_cost), 1),
+                Cell::new(formatted_gain, 1),
+                Cell::new(realized, 1),
+                Cell::new(render.number(income), 1),
+                Cell::new(generate_amount_string(total_return, render), 1),
+                Cell::new(generate_weight_string(holding_weight), 1),
+            ];
+            if let Some(benchmark) = benchmark {
+                let difference = metrics.change_percentage - benchmark.change_percentage;
+                cells.push(Cell::new(generate_benchmark_string(difference, render), 1));
+            }
+            table.add_row(Row::new(cells));
+        }
+
+        for stock in unavailable {
+            let columns = if benchmark.is_some() { 12 } else { 11 };
+            table.add_row(unavailable_row(stock, columns));
+        }
+
+        if !self.portfolio.is_empty() {
+            for (currency, subtotal, subtotal_weight) in subtotals {
+                let label = format!("{} subtotal", currency.code());
+                let cells = subtotal.cells(label, subtotal_weight, benchmark, render);
+                table.add_row(Row::new(cells));
+            }
+
+            let label = match conversion {
+                Some(conversion) => Some(format!("Total ({})", conversion.base.code())),
+                None if mixed_currencies => None,
+                None => Some(String::from("Total")),
+            };
+            if let Some(label) = label {
+                let total_weight = Some(fraction_of(totals.value, totals.value));
+                let cells = totals.cells(label, total_weight, benchmark, render);
+                table.add_row(Row::new(cells));
+            }
+        }
+
+        let mut s = format!("{}\n", render.table(table));
+        s += &*reached_targets(&holdings);
+
+        Ok(s)
+    }
+
+    fn render_archive(
+        &self,
+        render: RenderOptions,
+        limit: Option<usize>,
+        page: usize,
+        method: CostMethod,
+    ) -> Result<String, StockoError> {
+        let mut table = Table::new();
+
+        table.add_row(Row::new(vec![Cell::new_with_alignment(
+            "Archive",
+            3,
+            Alignment::Center,
+        )]));
+
+        table.add_row(Row::new(vec![
+            Cell::new("Symbol", 1),
+            Cell::new("Orders", 1),
+            Cell::new("Gain", 1),
+        ]));
+
+        let mut total_spent = 0.0;
+        let mut total_gain = 0.0;
+
+        for stock in self.archive.values() {
+            let order_metrics = stock.calculate_order_metrics_by(method);
+            total_spent += order_metrics.total_spent;
+            total_gain += order_metrics.realized_gain;
+        }
+
+        // Most recently closed positions first, stocks archived before close dates were
+        // recorded go last
+        let mut stocks: Vec<&Stock> = self.archive.values().collect();
+        stocks.sort_by(|a, b| {
+            b.archived_on
+                .cmp(&a.archived_on)
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+
+        let shown: Vec<&Stock> = match limit {
+            Some(limit) => stocks
+                .iter()
+                .cloned()
+                .skip(page.saturating_sub(1) * limit)
+                .take(limit)
+                .collect(),
+            None => stocks.clone(),
+        };
+
+        for stock in &shown {
+            let order_metrics = stock.calculate_order_metrics_by(method);
+
+            let (overall_gain, gain_percentage) = order_metrics.closed_gain();
+
+            let formatted_gain = generate_gain_string(overall_gain, gain_percentage, render);
+
+            let mut orders = String::new();
+
+            for order in stock.orders_by_date() {
+                orders += &*format!("{}\n", order.describe(render));
+            }
+            orders.pop();
+
+            let row = Row::new(vec![
+                Cell::new(stock.symbol.clone(), 1),
+                Cell::new(orders, 1),
+                Cell::new(formatted_gain, 1),
+            ]);
+            table.add_row(row);
+        }
+
+        let total_gain_percentage = fraction_of(total_gain, total_spent);
+
+        let formatted_total_gain = generate_gain_string(total_gain, total_gain_percentage, render);
+
+        table.add_row(Row::new(vec![
+            Cell::new("Total Gain", 2),
+            Cell::new(formatted_total_gain, 1),
+        ]));
+
+        let hidden = stocks.len() - shown.len();
+        if hidden > 0 {
+            table.add_row(Row::new(vec![Cell::new_with_alignment(
+                format!("{} more archived stocks not shown", hidden),
+                3,
+                Alignment::Center,
+            )]));
+        }
+
+        Ok(format!("{}\n", render.table(table)))
+    }
+}
+
+fn main() -> Result<(), StockoError> {
+    let app = App::new("managed-alias")
+        .version("1.0")
+        .author("Ryan Bluth")
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .help("Output format, csv is only supported by export")
+                .takes_value(true)
+                .possible_values(&["table", "json", "csv"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("no_color")
+                .long("no-color")
+                .help("Disables coloured output, as does setting NO_COLOR")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("plain")
+                .long("plain")
+                .help("Prints tab separated columns instead of bordered tables")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("precision")
+                .long("precision")
+                .help("Decimal places of prices and amounts, defaults to 2")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .help("Number of times to retry a failed request, defaults to STOCKO_RETRIES or 3")
+                .takes_value(true)
+                .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .help("Most requests to make at the same time, defaults to 5")
+                .takes_value(true)
+                .validator(|v| match v.parse::<usize>() {
+                    Ok(x) if x > 0 => Ok(()),
+                    _ => Err(String::from("the concurrency must be at least 1")),
+                })
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("account")
+                .long("account")
+                .help("Account to use, defaults to the default account")
+                .takes_value(true)
+                .validator(|v| {
+                    if v.trim().is_empty() {
+                        Err(String::from("the account name cannot be empty"))
+                    } else {
+                        Ok(())
+                    }
+                })
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .help("Period each price change covers")
+                .takes_value(true)
+                .possible_values(&["daily", "weekly", "monthly"])
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .alias("l")
+                .about("Displays all stocks in portfolio")
+                .arg(
+                    Arg::with_name("archive_limit")
+                        .long("archive-limit")
+                        .help("Maximum number of archived stocks to display")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("archive_page")
+                        .long("archive-page")
+                        .help("Page of archived stocks to display, starting at 1")
+                        .takes_value(true)
+                        .requires("archive_limit")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .short("j")
+                        .long("json")
+                        .help("Prints the stocks as JSON instead of tables"),
+                )
+                .arg(
+                    Arg::with_name("only")
+                        .long("only")
+                        .help("Only displays a single section")
+                        .takes_value(true)
+                        .possible_values(&["portfolio", "watchlist", "archive"])
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .help("Field to order the portfolio and watch list by")
+                        .takes_value(true)
+                        .possible_values(&["symbol", "price", "change", "gain", "value"])
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("desc")
+                        .long("desc")
+                        .help("Sorts from highest to lowest"),
+                )
+                .arg(
+                    Arg::with_name("base_currency")
+                        .long("base-currency")
+                        .help("Currency to convert the portfolio totals into, CAD, USD, GBP or AUD")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("refresh")
+                        .long("refresh")
+                        .help("Redraws the list every given number of seconds, at least 60")
+                        .takes_value(true)
+                        .validator(|v| match v.parse::<u64>() {
+                            Ok(x) if x >= MIN_REFRESH_SECS => Ok(()),
+                            _ => Err(format!(
+                                "the refresh interval must be at least {} seconds",
+                                MIN_REFRESH_SECS
+                            )),
+                        })
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("change_days")
+                        .long("change-days")
+                        .help("Number of closes back to measure the change from, defaults to 1")
+                        .takes_value(true)
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(x) if x > 0 => Ok(()),
+                            _ => Err(String::from("the number of days must be at least 1")),
+                        })
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("offline")
+                        .long("offline")
+                        .help("Shows the last fetched prices instead of fetching"),
+                )
+                .arg(
+                    Arg::with_name("benchmark")
+                        .long("benchmark")
+                        .help("Compares each change in the portfolio to a symbol, defaults to SPY")
+                        .takes_value(true)
+                        .min_values(0)
+                        .max_values(1)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .help("How the cost of sold shares is worked out, defaults to average")
+                        .takes_value(true)
+                        .possible_values(&["average", "fifo", "lifo"])
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("Displays the totals for your portfolio without the tables")
+                .arg(
+                    Arg::with_name("base_currency")
+                        .long("base-currency")
+                        .help("Currency to convert the totals into, CAD, USD, GBP or AUD")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .help("How the cost of sold shares is worked out, defaults to average")
+                        .takes_value(true)
+                        .possible_values(&["average", "fifo", "lifo"])
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .alias("w")
+                .about("Displays all stocks in portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .long("exchange")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("quote")
+                .alias("q")
+                .about("Displays the current price of a stock without saving it")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .long("exchange")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Draws the recent closes of a stock as a sparkline")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .long("exchange")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("days")
+                        .long("days")
+                        .help("Number of closes to draw, defaults to 30")
+                        .takes_value(true)
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(x) if x > 0 => Ok(()),
+                            _ => Err(String::from("the number of days must be at least 1")),
+                        })
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("movers")
+                .about("Shows the stocks which changed the most")
+                .arg(
+                    Arg::with_name("direction")
+                        .help("Biggest gains or biggest losses, defaults to top")
+                        .index(1)
+                        .possible_values(&["top", "worst"])
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .short("n")
+                        .long("limit")
+                        .help("Number of stocks to show, defaults to 5")
+                        .takes_value(true)
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(x) if x > 0 => Ok(()),
+                            _ => Err(String::from("the limit must be at least 1")),
+                        })
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("only")
+                        .long("only")
+                        .help("Only looks at a single section")
+                        .takes_value(true)
+                        .possible_values(&["portfolio", "watchlist"])
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("search")
+                .about("Looks up symbols matching a company name or ticker")
+                .arg(
+                    Arg::with_name("query")
+                        .help("Text to search for")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("unwatch")
+                .alias("uw")
+                .about("Removes a stock from your watch list")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .long("exchange")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("buy")
+                .alias("b")
+                .about("Add shares to your portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("shares")
+                        .short("s")
+                        .help("Number of shares")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("share_price")
+                        .short("p")
+                        .help("Share Price")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .help("Date of the trade as YYYY-MM-DD, defaults to today")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .long("fee")
+                        .help("Commission paid on the trade")
+                        .takes_value(true)
+                        .validator(validate_fee)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sell")
+                .alias("s")
+                .about("Remove shares to your portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("shares")
+                        .short("s")
+                        .help("Number of shares")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("share_price")
+                        .short("p")
+                        .help("Share Price")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .help("Date of the trade as YYYY-MM-DD, defaults to today")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .long("fee")
+                        .help("Commission paid on the trade")
+                        .takes_value(true)
+                        .validator(validate_fee)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Archive a fully sold position without asking"),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("move")
+                .alias("mv")
+                .about("Moves a stock from your watch list into your portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("shares")
+                        .short("s")
+                        .long("shares")
+                        .help("Number of shares")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("share_price")
+                        .short("p")
+                        .long("price")
+                        .help("Share Price")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .help("Date of the trade as YYYY-MM-DD, defaults to today")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .long("fee")
+                        .help("Commission paid on the trade")
+                        .takes_value(true)
+                        .validator(validate_fee)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-order")
+                .alias("ro")
+                .about("Removes an order from a stock in your portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("index")
+                        .short("i")
+                        .long("index")
+                        .help("Position of the order when listed by date, from 0")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set-target")
+                .about("Flags a stock in list once it reaches a price, a price of 0 clears it")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("price")
+                        .short("p")
+                        .long("price")
+                        .help("Target Price")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dividend")
+                .about("Records a dividend paid by a stock in your portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .short("a")
+                        .long("amount")
+                        .help("Total amount received")
+                        .takes_value(true)
+                        .validator(validate_dividend_amount)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .help("Date of the payment as YYYY-MM-DD, defaults to today")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clear-target")
+                .about("Removes the target price of a stock")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rename")
+                .about("Changes the symbol of a stock, keeping its orders")
+                .arg(
+                    Arg::with_name("old_symbol")
+                        .help("Current Stock Symbol")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("new_symbol")
+                        .help("New Stock Symbol")
+                        .index(2)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes every order in your portfolio and archive as CSV")
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("File to write to, defaults to stdout")
+                        .takes_value(true)
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("accounts")
+                .about("Lists your accounts and how many stocks are in each"),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Adds the orders in a CSV file, in the format written by export")
+                .arg(
+                    Arg::with_name("path")
+                        .help("CSV file to read")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("strict")
+                        .long("strict")
+                        .help("Import nothing if any row is invalid")
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clear")
+                .alias("reset")
+                .about("Removes every stock from a collection")
+                .arg(
+                    Arg::with_name("collection")
+                        .help("Collection to clear")
+                        .index(1)
+                        .possible_values(&["portfolio", "watchlist", "archive", "all"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Skip the confirmation prompt"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("Prints what would be removed without removing it"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Allows clearing the portfolio, which removes open positions"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("split")
+                .about("Applies a stock split to the shares in your portfolio")
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("e")
+                        .help("Exchange Symbol")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Stock Symbol")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("ratio")
+                        .help("Split ratio as new:old, e.g. 2:1 or 1:10 for a reverse split")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("rounding")
+                        .long("rounding")
+                        .help("How to round a position that no longer has a whole number of shares")
+                        .takes_value(true)
+                        .possible_values(&["floor", "nearest", "keep-fractional"])
+                        .default_value("floor"),
+                ),
+        );
+
+    #[cfg(unix)]
+    let app = app.subcommand(
+        SubCommand::with_name("tui")
+            .about("Opens an interactive dashboard of your portfolio, watch list and archive"),
+    );
+
+    let matches = app.get_matches();
+
+    let list_json = matches
+        .subcommand_matches("list")
+        .is_some_and(|sub_matches| sub_matches.is_present("json"));
+
+    let format = if list_json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::from_name(global_value_of(&matches, "format"))
+    };
+
+    let render = RenderOptions {
+        color: ColorMode::from_flag(global_is_present(&matches, "no_color")),
+        plain: global_is_present(&matches, "plain"),
+        precision: global_value_of(&matches, "precision")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(DEFAULT_PRECISION),
+    };
+
+    if let Some(account) = global_value_of(&matches, "account") {
+        ACCOUNT.set(String::from(account.trim())).unwrap();
+    }
+
+    let result = run(&matches, format, render);
+
+    if format == OutputFormat::Json {
+        if let Err(ref e) = result {
+            let report = ErrorReport {
+                error: e.message(),
+                kind: e.kind(),
+            };
+            println!("{}", serde_json::to_string(&report).unwrap());
+            std::process::exit(1);
+        }
+    }
+
+    result
+}
+
+/// Global arguments can be given before or after the subcommand, so check both places
+fn global_value_of<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches
+        .subcommand()
+        .1
+        .and_then(|sub_matches| sub_matches.value_of(name))
+        .or_else(|| matches.value_of(name))
+}
+
+fn global_is_present(matches: &ArgMatches, name: &str) -> bool {
+    matches.is_present(name)
+        || matches
+            .subcommand()
+            .1
+            .is_some_and(|sub_matches| sub_matches.is_present(name))
+}
+
+fn run(
+    matches: &ArgMatches,
+    format: OutputFormat,
+    render: RenderOptions,
+) -> Result<(), StockoError> {
+    let options = FetchOptions {
+        retries: get_retries(global_value_of(matches, "retries")),
+        interval: Interval::from_name(global_value_of(matches, "interval")),
+        concurrency: match global_value_of(matches, "concurrency") {
+            Some(concurrency) => concurrency.parse().unwrap(),
+            None => DEFAULT_CONCURRENCY,
+        },
+    };
+    let fetcher = AlphaVantageFetcher { options };
+
+    #[cfg(unix)]
+    {
+        if matches.subcommand_matches("tui").is_some() {
+            return tui::run(render, options, &fetcher);
+        }
+    }
+
+    if let Some(sub_matches) = matches.subcommand_matches("list") {
+        let archive_limit = if sub_matches.is_present("archive_limit") {
+            Some(value_t!(sub_matches, "archive_limit", usize).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        };
+        let archive_page = if sub_matches.is_present("archive_page") {
+            value_t!(sub_matches, "archive_page", usize).unwrap_or_else(|e| e.exit())
+        } else {
+            1
+        };
+        let only = match sub_matches.value_of("only") {
+            Some(section) => Collection::from_name(section)?,
+            None => Collection::All,
+        };
+        let base_currency = match sub_matches.value_of("base_currency") {
+            Some(currency) => Some(Currency::from_name(currency)?),
+            None => None,
+        };
+        let list_options = ListOptions {
+            only,
+            base_currency,
+            archive_limit,
+            archive_page,
+            sort: SortOrder {
+                field: SortField::from_name(sub_matches.value_of("sort")),
+                descending: sub_matches.is_present("desc"),
+            },
+            offline: sub_matches.is_present("offline"),
+            benchmark: if sub_matches.is_present("benchmark") {
+                let symbol = sub_matches
+                    .value_of("benchmark")
+                    .unwrap_or(DEFAULT_BENCHMARK);
+                Some(symbol.to_uppercase())
+            } else {
+                None
+            },
+            method: CostMethod::from_name(sub_matches.value_of("method")),
+            change_days: if sub_matches.is_present("change_days") {
+                value_t!(sub_matches, "change_days", usize).unwrap_or_else(|e| e.exit())
+            } else {
+                1
+            },
+            refresh: if sub_matches.is_present("refresh") {
+                Some(value_t!(sub_matches, "refresh", u64).unwrap_or_else(|e| e.exit()))
+            } else {
+                None
+            },
+        };
+        list(format, render, list_options, options, &fetcher)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("summary") {
+        let base_currency = match sub_matches.value_of("base_currency") {
+            Some(currency) => Some(Currency::from_name(currency)?),
+            None => None,
+        };
+        let method = CostMethod::from_name(sub_matches.value_of("method"));
+        summary(render, base_currency, method, options, &fetcher)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("watch") {
+        let exchange_value = sub_matches.value_of("exchange");
+        let symbol = symbol_arg(sub_matches)?;
+        watch(symbol, exchange_value, &fetcher)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("quote") {
+        let symbol = symbol_arg(sub_matches)?;
+        print!("{}", render_quote(symbol, format, render, &fetcher)?);
+    } else if let Some(sub_matches) = matches.subcommand_matches("history") {
+        let symbol = symbol_arg(sub_matches)?;
+        let days = if sub_matches.is_present("days") {
+            value_t!(sub_matches, "days", usize).unwrap_or_else(|e| e.exit())
+        } else {
+            DEFAULT_HISTORY_DAYS
+        };
+        history(symbol, days, format, render, &fetcher)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("movers") {
+        let direction = MoverDirection::from_name(sub_matches.value_of("direction"));
+        let limit = if sub_matches.is_present("limit") {
+            value_t!(sub_matches, "limit", usize).unwrap_or_else(|e| e.exit())
+        } else {
+            DEFAULT_MOVERS_LIMIT
+        };
+        let only = match sub_matches.value_of("only") {
+            Some(name) => Collection::from_name(name)?,
+            None => Collection::All,
+        };
+        movers(direction, limit, only, format, render, options, &fetcher)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("search") {
+        let query = sub_matches.value_of("query").unwrap();
+        print!("{}", render_search(query, format, render, &fetcher)?);
+    } else if let Some(sub_matches) = matches.subcommand_matches("unwatch") {
+        let symbol = symbol_arg(sub_matches)?;
+        unwatch(symbol)?;
+    } else if matches.subcommand_matches("buy").is_some()
+        || matches.subcommand_matches("sell").is_some()
+    {
+        let sub_matches = matches
+            .subcommand_matches("buy")
+            .unwrap_or_else(|| matches.subcommand_matches("sell").unwrap());
+        let exchange_value = sub_matches.value_of("exchange");
+        let symbol = symbol_arg(sub_matches)?;
+        let mut shares = value_t!(sub_matches, "shares", f64).unwrap_or_else(|e| e.exit());
+        let price = value_t!(sub_matches, "share_price", f64).unwrap_or_else(|e| e.exit());
+        if matches.subcommand_matches("sell").is_some() {
+            shares *= -1.0;
+        }
+        let date = match sub_matches.value_of("date") {
+            Some(date) => parse_date(date)?,
+            None => today(),
+        };
+        let order = Order {
+            shares,
+            share_price: price,
+            date: Some(date),
+            fee: parse_fee(sub_matches),
+        };
+        let skip_confirmation = sub_matches.is_present("yes");
+        process_order(
+            symbol,
+            exchange_value,
+            order,
+            &fetcher,
+            skip_confirmation,
+            &mut ask_terminal,
+        )?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("move") {
+        let symbol = symbol_arg(sub_matches)?;
+        let shares = value_t!(sub_matches, "shares", f64).unwrap_or_else(|e| e.exit());
+        let price = value_t!(sub_matches, "share_price", f64).unwrap_or_else(|e| e.exit());
+        let date = match sub_matches.value_of("date") {
+            Some(date) => parse_date(date)?,
+            None => today(),
+        };
+        let fee = parse_fee(sub_matches);
+        move_to_portfolio(symbol, shares, price, date, fee)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("remove-order") {
+        let symbol = symbol_arg(sub_matches)?;
+        let index = value_t!(sub_matches, "index", usize).unwrap_or_else(|e| e.exit());
+        remove_order(symbol, index, render)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("set-target") {
+        let symbol = symbol_arg(sub_matches)?;
+        let price = value_t!(sub_matches, "price", f64).unwrap_or_else(|e| e.exit());
+        let target = if price > 0.0 { Some(price) } else { None };
+        set_target(symbol, target)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("dividend") {
+        let symbol = symbol_arg(sub_matches)?;
+        let amount = value_t!(sub_matches, "amount", f64).unwrap_or_else(|e| e.exit());
+        let date = match sub_matches.value_of("date") {
+            Some(date) => parse_date(date)?,
+            None => today(),
+        };
+        add_dividend(symbol, amount, date)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("clear-target") {
+        let symbol = symbol_arg(sub_matches)?;
+        set_target(symbol, None)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("rename") {
+        let old_symbol = sub_matches.value_of("old_symbol").unwrap().to_uppercase();
+        let new_symbol = sub_matches.value_of("new_symbol").unwrap().to_uppercase();
+        rename(old_symbol, new_symbol)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("export") {
+        export(
+            global_value_of(matches, "format"),
+            sub_matches.value_of("output"),
+        )?;
+    } else if matches.subcommand_matches("accounts").is_some() {
+        accounts(format)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("import") {
+        import(
+            sub_matches.value_of("path").unwrap(),
+            sub_matches.is_present("strict"),
+            &fetcher,
+        )?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("clear") {
+        let target = Collection::from_name(sub_matches.value_of("collection").unwrap())?;
+        let clear_options = ClearOptions {
+            skip_confirmation: sub_matches.is_present("yes"),
+            dry_run: sub_matches.is_present("dry_run"),
+            force: sub_matches.is_present("force"),
+        };
+        clear(target, clear_options)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("split") {
+        let symbol = symbol_arg(sub_matches)?;
+        let (num, den) = parse_split_ratio(sub_matches.value_of("ratio").unwrap())?;
+        let rounding = Rounding::from_name(sub_matches.value_of("rounding"));
+        split(symbol, num, den, rounding)?;
+    }
+    Ok(())
+}
+
+fn watch(
+    symbol: String,
+    exchange_symbol: Option<&str>,
+    fetcher: &dyn Fetcher,
+) -> Result<(), StockoError> {
+    let mut collections = load_data()?;
+    validate_symbol(&symbol, fetcher)?;
+    // Run a fetch to make sure things are working
+    fetcher.fetch(symbol.as_str())?;
+    let exchange = Exchange::from_symbol(exchange_symbol)?;
+    let stock = Stock {
+        currency: Some(exchange.currency()),
+        exchange,
+        symbol: symbol.clone(),
+        orders: Vec::new(),
+        ..Default::default()
+    };
+
+    collections.watchlist.insert(symbol, stock);
+    save_data(collections)?;
+    Ok(())
+}
+
+fn render_quote(
+    symbol: String,
+    format: OutputFormat,
+    render: RenderOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<String, StockoError> {
+    let time_series = fetcher.fetch(&symbol)?;
+    let metrics = calculate_stock_metrics(&symbol, &time_series, 1)?;
+
+    if format == OutputFormat::Json {
+        let entry = ReportEntry {
+            symbol,
+            close_today: Some(metrics.close_today),
+            as_of: Some(metrics.date.clone()),
+            change: Some(metrics.change),
+            change_percentage: Some(metrics.change_percentage),
+            ..Default::default()
+        };
+        let json = serde_json::to_string_pretty(&entry).unwrap();
+        return Ok(format!("{}\n", json));
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Symbol", 1),
+        Cell::new("Price", 1),
+        Cell::new("Change", 1),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new(symbol, 1),
+        Cell::new(generate_price_string(&metrics, render), 1),
+        Cell::new(generate_change_string(&metrics, render), 1),
+    ]));
+
+    Ok(format!("{}\n", render.table(table)))
+}
+
+#[derive(Serialize)]
+struct History {
+    symbol: String,
+    closes: Vec<f64>,
+    start: f64,
+    end: f64,
+    high: f64,
+    low: f64,
+}
+
+/// Draws the last `days` closes, or every close when the series is shorter than that
+fn history(
+    symbol: String,
+    days: usize,
+    format: OutputFormat,
+    render: RenderOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<(), StockoError> {
+    let time_series = fetcher.fetch(&symbol)?;
+    let closes = recent_closes(&time_series, days);
+
+    let (start, end) = match (closes.first(), closes.last()) {
+        (Some(&start), Some(&end)) => (start, end),
+        _ => return Err(StockoError::InsufficientData { symbol }),
+    };
+    let high = closes.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let low = closes.iter().cloned().fold(f64::INFINITY, f64::min);
+
+    if format == OutputFormat::Json {
+        let history = History {
+            symbol,
+            closes,
+            start,
+            end,
+            high,
+            low,
+        };
+        println!("{}", serde_json::to_string_pretty(&history).unwrap());
+        return Ok(());
+    }
+
+    println!("{}, last {} closes", symbol, closes.len());
+    println!("{}", generate_trend_string(&closes, render));
+    println!(
+        "Start {}  End {}  {}",
+        render.number(start),
+        render.number(end),
+        generate_gain_string(end - start, fraction_of(end - start, start), render)
+    );
+    println!("High  {}  Low {}", render.number(high), render.number(low));
+    Ok(())
+}
+
+/// The `limit` stocks with the largest change in `direction`, skipping with a warning those
+/// which have no quote in the cache
+fn rank_movers<'s>(
+    stocks: Vec<&'s Stock>,
+    cache: &QuoteCache,
+    direction: MoverDirection,
+    limit: usize,
+) -> Vec<(&'s Stock, StockMetrics)> {
+    let mut movers = Vec::new();
+    for stock in stocks {
+        // Symbols which failed to prefetch are not requested again
+        let time_series = match cache.series.get(&stock.symbol) {
+            Some((time_series, _)) => time_series,
+            None => {
+                eprintln!("Skipping {}, its quote could not be fetched", stock.symbol);
+                continue;
+            }
+        };
+        match calculate_stock_metrics(&stock.symbol, time_series, 1) {
+            Ok(metrics) => movers.push((stock, metrics)),
+            Err(e) => eprintln!("Skipping {}. Cause: {}", stock.symbol, e),
+        }
+    }
+
+    movers.sort_by(|a, b| {
+        let ordering =
+            a.1.change_percentage
+                .partial_cmp(&b.1.change_percentage)
+                .unwrap_or(Ordering::Equal);
+        match direction {
+            MoverDirection::Top => ordering.reverse(),
+            MoverDirection::Worst => ordering,
+        }
+    });
+    movers.truncate(limit);
+    movers
+}
+
+/// Prints the `limit` stocks in the portfolio and watch list with the largest change in the
+/// given direction. Stocks whose quote can't be fetched are skipped with a warning.
+fn movers(
+    direction: MoverDirection,
+    limit: usize,
+    only: Collection,
+    format: OutputFormat,
+    render: RenderOptions,
+    options: FetchOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<(), StockoError> {
+    let collection = load_data()?;
+
+    let mut stocks: Vec<&Stock> = Vec::new();
+    if only.includes(Collection::Portfolio) {
+        stocks.extend(collection.portfolio.values());
+    }
+    if only.includes(Collection::Watchlist) {
+        stocks.extend(collection.watchlist.values());
+    }
+    stocks.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    stocks.dedup_by(|a, b| a.symbol == b.symbol);
+
+    let mut cache = QuoteCache::new(fetcher);
+    if let Err(e) = cache.prefetch(
+        stocks.iter().map(|x| x.symbol.as_str()),
+        options.concurrency,
+    ) {
+        eprintln!("Not every quote could be fetched. Cause: {}", e);
+    }
+    let movers = rank_movers(stocks, &cache, direction, limit);
+
+    if format == OutputFormat::Json {
+        let entries: Vec<ReportEntry> = movers
+            .into_iter()
+            .map(|(stock, metrics)| ReportEntry {
+                symbol: stock.symbol.clone(),
+                currency: Some(stock.currency()),
+                close_today: Some(metrics.close_today),
+                as_of: Some(metrics.date.clone()),
+                change: Some(metrics.change),
+                change_percentage: Some(metrics.change_percentage),
+                ..Default::default()
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+        return Ok(());
+    }
+
+    let title = match direction {
+        MoverDirection::Top => "Top Movers",
+        MoverDirection::Worst => "Worst Movers",
+    };
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![Cell::new_with_alignment(
+        title,
+        3,
+        Alignment::Center,
+    )]));
+    table.add_row(Row::new(vec![
+        Cell::new("Symbol", 1),
+        Cell::new("Price", 1),
+        Cell::new("Change", 1),
+    ]));
+    for (stock, metrics) in &movers {
+        table.add_row(Row::new(vec![
+            Cell::new(stock.symbol.clone(), 1),
+            Cell::new(generate_price_string(metrics, render), 1),
+            Cell::new(generate_change_string(metrics, render), 1),
+        ]));
+    }
+
+    println!("{}", render.table(table));
+    Ok(())
+}
+
+fn render_search(
+    query: &str,
+    format: OutputFormat,
+    render: RenderOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<String, StockoError> {
+    let matches = fetcher.search(query)?;
+
+    if format == OutputFormat::Json {
+        let json = serde_json::to_string_pretty(&matches).unwrap();
+        return Ok(format!("{}\n", json));
+    }
+
+    if matches.is_empty() {
+        return Ok(format!("No symbols match {}\n", query));
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Symbol", 1),
+        Cell::new("Name", 1),
+        Cell::new("Region", 1),
+        Cell::new("Currency", 1),
+    ]));
+
+    for search_match in &matches {
+        table.add_row(Row::new(vec![
+            Cell::new(search_match.symbol.clone(), 1),
+            Cell::new(search_match.name.clone(), 1),
+            Cell::new(search_match.region.clone(), 1),
+            Cell::new(search_match.currency.clone(), 1),
+        ]));
+    }
+
+    Ok(format!("{}\n", render.table(table)))
+}
+
+fn unwatch(symbol: String) -> Result<(), StockoError> {
+    let mut collections = load_data()?;
+
+    if collections.watchlist.remove(&symbol).is_none() {
+        return Err(StockoError::SymbolNotInWatchlist(symbol));
+    }
+
+    save_data(collections)?;
+    println!("Removed {} from your watch list", symbol);
+    Ok(())
+}
+
+/// Buys a watched stock, removing it from the watch list. A stock which is already in the
+/// portfolio gets the order added to its existing ones.
+fn move_to_portfolio(
+    symbol: String,
+    shares: f64,
+    price: f64,
+    date: String,
+    fee: f64,
+) -> Result<(), StockoError> {
+    // Moving is always a buy, zero shares are left to validate_order
+    if shares < 0.0 {
+        return Err(StockoError::InvalidShareQuantity { symbol, shares });
+    }
+    validate_order(&symbol, shares, price)?;
+
+    let mut collections = load_data()?;
+
+    let mut stock = match collections.watchlist.remove(&symbol) {
+        Some(stock) => stock,
+        None => return Err(StockoError::SymbolNotInWatchlist(symbol)),
+    };
+
+    let order = Order {
+        shares,
+        share_price: price,
+        date: Some(date),
+        fee,
+    };
+
+    match collections.portfolio.get_mut(&symbol) {
+        Some(existing) => existing.orders.push(order),
+        None => {
+            stock.orders.push(order);
+            collections.portfolio.insert(symbol.clone(), stock);
+        }
+    }
+
+    save_data(collections)?;
+    println!("Moved {} from your watch list to your portfolio", symbol);
+    Ok(())
+}
+
+/// Rejects orders which would not change the position or have no sensible cost
+fn validate_order(symbol: &str, shares: f64, price: f64) -> Result<(), StockoError> {
+    if shares.is_nan() || shares.abs() < SHARE_EPSILON {
+        return Err(StockoError::ZeroShareOrder {
+            symbol: String::from(symbol),
+        });
+    }
+    if price.is_nan() || price <= 0.0 {
+        return Err(StockoError::InvalidPrice(price));
+    }
+    Ok(())
+}
+
+/// Records a buy or sell. A sell which closes the position asks `answer` before archiving it
+/// unless `skip_confirmation` is set, declining leaves the portfolio untouched
+fn process_order(
+    symbol: String,
+    exchange_symbol: Option<&str>,
+    order: Order,
+    fetcher: &dyn Fetcher,
+    skip_confirmation: bool,
+    answer: &mut dyn FnMut(&str) -> Result<bool, StockoError>,
+) -> Result<(), StockoError> {
+    validate_order(&symbol, order.shares, order.share_price)?;
+
+    let mut collection = load_data()?;
+    let exchange = Exchange::from_symbol(exchange_symbol)?;
+
+    if order.shares > 0.0 && !collection.portfolio.contains_key(&symbol) {
+        validate_symbol(&symbol, fetcher)?;
+    }
+
+    if collection.closes_position(&symbol, order.shares)
+        && !confirm_archive(&symbol, skip_confirmation, answer)?
+    {
+        println!("Aborted, the sell was not recorded");
+        return Ok(());
+    }
+
+    collection.apply_order(symbol, exchange, order)?;
+
+    save_data(collection)?;
+
+    Ok(())
+}
+
+/// Removes a mistaken order. A stock with no orders left is deleted, and one which no longer
+/// holds any shares is archived the same way selling everything would.
+fn remove_order(symbol: String, index: usize, render: RenderOptions) -> Result<(), StockoError> {
+    let mut collection = load_data()?;
+
+    let mut stock = match collection.portfolio.remove(&symbol) {
+        Some(stock) => stock,
+        None => return Err(StockoError::SymbolNotInPortfolio(symbol)),
+    };
+
+    let position = match stock.order_position(index) {
+        Some(position) => position,
+        None => return Err(StockoError::InvalidOrderIndex { symbol, index }),
+    };
+
+    let removed = stock.orders.remove(position);
+    let total_shares = stock.calculate_order_metrics().total_shares;
+
+    if total_shares < -SHARE_EPSILON {
+        return Err(StockoError::OversoldPosition { symbol });
+    }
+
+    if stock.orders.is_empty() {
+        println!("Removed {} from your portfolio", symbol);
+    } else if total_shares < SHARE_EPSILON {
+        stock.archived_on = Some(today());
+        collection.archive.insert(symbol.clone(), stock);
+        println!("Archived {} as no shares are left", symbol);
+    } else {
+        collection.portfolio.insert(symbol.clone(), stock);
+    }
+
+    save_data(collection)?;
+    println!("Removed order {} from {}", removed.describe(render), symbol);
+    Ok(())
+}
+
+/// Sets or, when `target` is `None`, removes the target price of a stock in the portfolio or
+/// watch list
+fn set_target(symbol: String, target: Option<f64>) -> Result<(), StockoError> {
+    let mut collection = load_data()?;
+
+    let stock = match collection.portfolio.get_mut(&symbol) {
+        Some(stock) => stock,
+        None => match collection.watchlist.get_mut(&symbol) {
+            Some(stock) => stock,
+            None => return Err(StockoError::UntrackedSymbol(symbol)),
+        },
+    };
+    stock.target_price = target;
+
+    save_data(collection)?;
+    match target {
+        Some(target) => println!("Set the target price of {} to {:.2}", symbol, target),
+        None => println!("Removed the target price of {}", symbol),
+    }
+    Ok(())
+}
+
+fn add_dividend(symbol: String, amount: f64, date: String) -> Result<(), StockoError> {
+    let mut collection = load_data()?;
+
+    match collection.portfolio.get_mut(&symbol) {
+        Some(stock) => stock.dividends.push(Dividend {
+            amount,
+            date: date.clone(),
+        }),
+        None => return Err(StockoError::SymbolNotInPortfolio(symbol)),
+    }
+
+    save_data(collection)?;
+    println!(
+        "Recorded a dividend of {:.2} from {} on {}",
+        amount, symbol, date
+    );
+    Ok(())
+}
+
+/// Moves a stock to a new symbol within the collection it is in
+fn rename(old_symbol: String, new_symbol: String) -> Result<(), StockoError> {
+    let mut collection = load_data()?;
+
+    let in_use = collection.portfolio.contains_key(&new_symbol)
+        || collection.watchlist.contains_key(&new_symbol)
+        || collection.archive.contains_key(&new_symbol);
+    if in_use {
+        return Err(StockoError::SymbolExists(new_symbol));
+    }
+
+    let stocks = vec![
+        &mut collection.portfolio,
+        &mut collection.watchlist,
+        &mut collection.archive,
+    ];
+    match stocks.into_iter().find(|x| x.contains_key(&old_symbol)) {
+        Some(stocks) => {
+            let mut stock = stocks.remove(&old_symbol).unwrap();
+            stock.symbol = new_symbol.clone();
+            stocks.insert(new_symbol.clone(), stock);
+        }
+        None => return Err(StockoError::SymbolNotFound(old_symbol)),
+    }
+
+    save_data(collection)?;
+    println!("Renamed {} to {}", old_symbol, new_symbol);
+    Ok(())
+}
+
+const EXPORT_HEADER: &str = "symbol,exchange,date,shares,share_price,fee";
+
+/// Writes the orders as CSV, which is the only export format so far
+/// `format` is the one asked for with `--format`, which can only be csv
+fn export(format: Option<&str>, output: Option<&str>) -> Result<(), StockoError> {
+    if let Some(format) = format.filter(|&x| x != "csv") {
+        return Err(StockoError::UnsupportedFormat(String::from(format)));
+    }
+
+    let collection = load_data()?;
+    let contents = orders_to_csv(&collection);
+
+    match output {
+        Some(path) => mapStockoErr!(StockoError::ExportError, fs::write(path, contents)),
+        None => {
+            print!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
+/// One row per order in the portfolio and archive, sorted by symbol then date
+fn orders_to_csv(collection: &StockCollections) -> String {
+    let mut stocks: Vec<&Stock> = collection
+        .portfolio
+        .values()
+        .chain(collection.archive.values())
+        .collect();
+    stocks.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    let mut csv = String::from(EXPORT_HEADER);
+    csv.push('\n');
+    for stock in stocks {
+        for order in stock.orders_by_date() {
+            csv += &format!(
+                "{},{},{},{},{},{}\n",
+                csv_field(&stock.symbol),
+                stock.exchange.name(),
+                order.date.as_deref().unwrap_or_default(),
+                order.shares,
+                order.share_price,
+                order.fee
+            );
+        }
+    }
+    csv
+}
+
+/// Quotes a field when it contains characters which would otherwise break up the row
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+/// Applies each row of the CSV as an order, saving once at the end. Invalid rows are reported
+/// by line number and skipped, or abort the import when strict is set
+fn import(path: &str, strict: bool, fetcher: &dyn Fetcher) -> Result<(), StockoError> {
+    let contents = mapStockoErr!(StockoError::ImportError, fs::read_to_string(path))?;
+    let mut rows = contents
+        .lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty());
+
+    let columns = match rows.next() {
+        Some((_, header)) => ImportColumns::from_header(&parse_csv_row(header))?,
+        None => return Err(StockoError::ImportError(String::from("the file is empty"))),
+    };
+
+    let mut collection = load_data()?;
+    let mut imported = 0;
+    let mut skipped = 0;
+
+    for (index, line) in rows {
+        match import_row(&mut collection, &columns, &parse_csv_row(line), fetcher) {
+            Ok(()) => imported += 1,
+            Err(cause) => {
+                let error = StockoError::InvalidImportRow {
+                    line: index + 1,
+                    cause,
+                };
+                if strict {
+                    return Err(error);
+                }
+                eprintln!("{}", error);
+                skipped += 1;
+            }
+        }
+    }
+
+    save_data(collection)?;
+    println!("Imported {} orders from {}", imported, path);
+    if skipped > 0 {
+        println!("Skipped {} invalid rows", skipped);
+    }
+    Ok(())
+}
+
+/// Positions of the import columns, found by name so the columns can come in any order
+struct ImportColumns {
+    symbol: usize,
+    exchange: usize,
+    shares: usize,
+    share_price: usize,
+    date: Option<usize>,
+    fee: Option<usize>,
+}
+
+impl ImportColumns {
+    fn from_header(header: &[String]) -> Result<ImportColumns, StockoError> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|x| x.trim().eq_ignore_ascii_case(name))
+        };
+        let require = |name: &str| {
+            find(name).ok_or_else(|| {
+                StockoError::ImportError(format!("the header has no {} column", name))
+            })
+        };
+
+        Ok(ImportColumns {
+            symbol: require("symbol")?,
+            exchange: require("exchange")?,
+            shares: require("shares")?,
+            share_price: require("share_price")?,
+            date: find("date"),
+            fee: find("fee"),
+        })
+    }
+}
+
+fn import_row(
+    collection: &mut StockCollections,
+    columns: &ImportColumns,
+    fields: &[String],
+    fetcher: &dyn Fetcher,
+) -> Result<(), String> {
+    let field = |index: usize| fields.get(index).map(|x| x.trim()).unwrap_or_default();
+    let optional_field = |index: Option<usize>| index.map(field).filter(|x| !x.is_empty());
+
+    let exchange_name = optional_field(Some(columns.exchange));
+    let exchange = Exchange::from_symbol(exchange_name).map_err(|e| e.to_string())?;
+
+    if field(columns.symbol).is_empty() {
+        return Err(String::from("the symbol is missing"));
+    }
+    let symbol =
+        normalize_symbol(field(columns.symbol), exchange_name).map_err(|e| e.to_string())?;
+
+    let shares = field(columns.shares)
+        .parse::<f64>()
+        .map_err(|_| format!("invalid share quantity {}", field(columns.shares)))?;
+    let share_price = field(columns.share_price)
+        .parse::<f64>()
+        .map_err(|_| format!("invalid share price {}", field(columns.share_price)))?;
+    let fee = match optional_field(columns.fee) {
+        Some(fee) => match fee.parse::<f64>() {
+            Ok(fee) if fee >= 0.0 => fee,
+            _ => return Err(format!("invalid fee {}", fee)),
+        },
+        None => 0.0,
+    };
+    let date = match optional_field(columns.date) {
+        Some(date) => parse_date(date).map_err(|e| e.to_string())?,
+        None => today(),
+    };
+
+    validate_order(&symbol, shares, share_price).map_err(|e| e.to_string())?;
+
+    if shares > 0.0 && !collection.portfolio.contains_key(&symbol) {
+        validate_symbol(&symbol, fetcher).map_err(|e| e.to_string())?;
+    }
+
+    let order = Order {
+        shares,
+        share_price,
+        date: Some(date),
+        fee,
+    };
+    collection
+        .apply_order(symbol, exchange, order)
+        .map_err(|e| e.to_string())
+}
+
+/// Splits a CSV row into fields, undoing the quoting applied by csv_field
+fn parse_csv_row(row: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn split(symbol: String, num: u32, den: u32, rounding: Rounding) -> Result<(), StockoError> {
+    let mut collection = load_data()?;
+
+    let cash_in_lieu_shares = match collection.portfolio.get_mut(&symbol) {
+        Some(stock) => {
+            stock.apply_split(num, den, rounding);
+            stock.splits.last().unwrap().cash_in_lieu_shares
+        }
+        None => return Err(StockoError::SymbolNotInPortfolio(symbol)),
+    };
+
+    save_data(collection)?;
+    println!("Applied {}:{} split to {}", num, den, symbol);
+    if cash_in_lieu_shares > 0.0 {
+        println!("{:.4} shares paid as cash in lieu", cash_in_lieu_shares);
+    }
+    Ok(())
+}
+
+fn parse_split_ratio(ratio: &str) -> Result<(u32, u32), StockoError> {
+    let mut parts = ratio.splitn(2, ':');
+    let num = parts.next().and_then(|x| x.trim().parse::<u32>().ok());
+    let den = parts.next().and_then(|x| x.trim().parse::<u32>().ok());
+
+    match (num, den) {
+        (Some(num), Some(den)) if num > 0 && den > 0 => Ok((num, den)),
+        _ => Err(StockoError::InvalidSplitRatio(String::from(ratio))),
+    }
+}
+
+fn validate_fee(fee: String) -> Result<(), String> {
+    match fee.parse::<f64>() {
+        Ok(fee) if fee >= 0.0 => Ok(()),
+        _ => Err(String::from("the fee must be a number of at least 0")),
+    }
+}
+
+fn validate_dividend_amount(amount: String) -> Result<(), String> {
+    match amount.parse::<f64>() {
+        Ok(amount) if amount > 0.0 => Ok(()),
+        _ => Err(String::from("the amount must be a number greater than 0")),
+    }
+}
+
+fn parse_fee(matches: &ArgMatches) -> f64 {
+    if matches.is_present("fee") {
+        value_t!(matches, "fee", f64).unwrap_or_else(|e| e.exit())
+    } else {
+        0.0
+    }
+}
+
+/// Accepts dates as YYYY-MM-DD and returns them in that form with zero padding
+fn parse_date(date: &str) -> Result<String, StockoError> {
+    match NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+        Ok(parsed) => Ok(parsed.format("%Y-%m-%d").to_string()),
+        Err(_) => Err(StockoError::InvalidDate(String::from(date))),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClearOptions {
+    skip_confirmation: bool,
+    /// Only prints the stocks which would be removed
+    dry_run: bool,
+    /// Needed to clear a portfolio which still holds stocks
+    force: bool,
+}
+
+fn clear(target: Collection, clear_options: ClearOptions) -> Result<(), StockoError> {
+    let mut collections = load_data()?;
+
+    let count = collections.count(target);
+    if count == 0 {
+        println!("Nothing to clear in {}", target.name());
+        return Ok(());
+    }
+
+    // A dry run removes nothing, so it never needs --force
+    if clear_options.dry_run {
+        println!("Would remove {} stocks from {}:", count, target.name());
+        for symbol in collections.symbols(target) {
+            println!("  {}", symbol);
+        }
+        return Ok(());
+    }
+
+    if target.includes(Collection::Portfolio)
+        && !collections.portfolio.is_empty()
+        && !clear_options.force
+    {
+        return Err(StockoError::ClearPortfolioWithoutForce);
+    }
+
+    let prompt = format!("Remove {} stocks from {}?", count, target.name());
+    if !clear_options.skip_confirmation && !confirm(&prompt)? {
+        println!("Aborted, nothing was removed");
+        return Ok(());
+    }
+
+    let removed = collections.clear(target);
+    save_data(collections)?;
+    println!("Removed {} stocks from {}", removed, target.name());
+    Ok(())
+}
+
+fn confirm_archive(
+    symbol: &str,
+    skip_confirmation: bool,
+    answer: &mut dyn FnMut(&str) -> Result<bool, StockoError>,
+) -> Result<bool, StockoError> {
+    if skip_confirmation {
+        return Ok(true);
+    }
+    answer(&format!("Fully sold {}; archive this position?", symbol))
+}
+
+/// Proceeds without asking when stdin is not a terminal so scripts keep working
+fn ask_terminal(prompt: &str) -> Result<bool, StockoError> {
+    if !io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    confirm(prompt)
+}
+
+fn confirm(prompt: &str) -> Result<bool, StockoError> {
+    print!("{} [y/N] ", prompt);
+    mapStockoErr!(StockoError::ReadInputError, io::stdout().flush())?;
+
+    let mut answer = String::new();
+    mapStockoErr!(
+        StockoError::ReadInputError,
+        io::stdin().read_line(&mut answer)
+    )?;
+
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// The `--retries` value wins over `STOCKO_RETRIES`, an unparsable variable is ignored
+fn get_retries(flag: Option<&str>) -> u32 {
+    flag.map(String::from)
+        .or_else(|| std::env::var(RETRIES_VAR).ok())
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_RETRIES)
+}
+
+fn fetch_symbol_time_series(
+    symbol: &str,
+    options: FetchOptions,
+) -> Result<TimeSeries, StockoError> {
+    retry_with_backoff(options.retries, RETRY_BASE_DELAY, || {
+        request_time_series(symbol, options.interval)
+    })
+}
+
+/// Calls `f` until it succeeds, fails with a non-transient error, or has been retried `retries`
+/// times. The delay doubles after each attempt, starting at `base_delay`
+fn retry_with_backoff<T, F>(retries: u32, base_delay: Duration, mut f: F) -> Result<T, StockoError>
+where
+    F: FnMut() -> Result<T, StockoError>,
+{
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(ref e) if e.is_transient() && attempt < retries => {
+                let delay = base_delay * (1 << attempt);
+                eprintln!("{}, retrying in {}s", e, delay.as_secs());
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn request_time_series(symbol: &str, interval: Interval) -> Result<TimeSeries, StockoError> {
+    // The alphavantage client reports every failed response as missing entries, so the request
+    // is made here to be able to tell rate limiting and unknown symbols apart
+    let body = request_alphavantage(&[("function", interval.function()), ("symbol", symbol)])?;
+
+    parse_time_series_response(symbol, interval, &body)
+}
+
+/// STOCKO_API_URL can point requests at a proxy which speaks the AlphaVantage API
+fn api_url() -> String {
+    match std::env::var(API_URL_VAR) {
+        Ok(ref url) if !url.is_empty() => url.clone(),
+        _ => String::from(ALPHAVANTAGE_URL),
+    }
+}
+
+/// Makes a request to the AlphaVantage query endpoint with the API key added and returns the
+/// body of the response
+fn request_alphavantage(query: &[(&str, &str)]) -> Result<String, StockoError> {
+    let api_key = get_api_key()?;
+    let client = reqwest::Client::new();
+    let mut response = mapStockoErr!(
+        StockoError::AlphaVantageError,
+        client
+            .get(&api_url())
+            .query(query)
+            .query(&[("apikey", api_key.as_str())])
+            .send()
+    )?;
+
+    check_status(response.status())?;
+    mapStockoErr!(StockoError::AlphaVantageError, response.text())
+}
+
+/// Server errors are worth retrying, unlike the errors AlphaVantage reports in the body
+fn check_status(status: reqwest::StatusCode) -> Result<(), StockoError> {
+    if status.is_server_error() {
+        return Err(StockoError::ApiServerError(status.to_string()));
+    }
+    Ok(())
+}
+
+/// Makes sure AlphaVantage lists `symbol` on its exchange before it gets saved
+fn validate_symbol(symbol: &str, fetcher: &dyn Fetcher) -> Result<(), StockoError> {
+    let wanted = search_result_symbol(symbol);
+    let found = fetcher
+        .search(symbol)?
+        .iter()
+        .any(|x| x.symbol.to_uppercase() == wanted);
+
+    if found {
+        Ok(())
+    } else {
+        Err(StockoError::UnknownSymbol {
+            symbol: String::from(symbol),
+        })
+    }
+}
+
+/// The symbol as the search lists it. Search results use their own exchange suffixes, SHOP.TRT
+/// rather than SHOP.TO for example, and symbols without a known suffix are left as they are.
+fn search_result_symbol(symbol: &str) -> String {
+    let symbol = symbol.to_uppercase();
+    for info in EXCHANGES.iter().filter(|x| !x.suffix.is_empty()) {
+        if let Some(base) = symbol.strip_suffix(info.suffix) {
+            return format!("{}{}", base, info.search_suffix);
+        }
+    }
+    symbol
+}
+
+fn parse_symbol_search_response(body: &str) -> Result<Vec<SearchMatch>, StockoError> {
+    let mut response = mapStockoErr!(
+        StockoError::DeserializationError,
+        serde_json::from_str::<serde_json::Value>(body)
+    )?;
+
+    if let Some(matches) = response.get_mut("bestMatches") {
+        return mapStockoErr!(
+            StockoError::DeserializationError,
+            serde_json::from_value::<Vec<SearchMatch>>(matches.take())
+        );
+    }
+
+    if let Some(note) = response.get("Note").or_else(|| response.get("Information")) {
+        return Err(StockoError::RateLimited(
+            note.as_str().unwrap_or_default().to_string(),
+        ));
+    }
+
+    Err(StockoError::DeserializationError(String::from(
+        "response did not contain any search results",
+    )))
+}
+
+/// Rate for converting `from` into `to`, taken from the config when it has one
+fn get_fx_rate(from: Currency, to: Currency, fetcher: &dyn Fetcher) -> Result<f64, StockoError> {
+    if from == to {
+        return Ok(1.0);
+    }
+
+    let key = format!("{}/{}", from.code(), to.code());
+    if let Some(&rate) = load_config()?.fx_rates.get(&key) {
+        return Ok(rate);
+    }
+
+    fetcher.fx_rate(from, to)
+}
+
+fn parse_fx_rate_response(pair: &str, body: &str) -> Result<f64, StockoError> {
+    let response = mapStockoErr!(
+        StockoError::DeserializationError,
+        serde_json::from_str::<serde_json::Value>(body)
+    )?;
+
+    let rate = response
+        .get("Realtime Currency Exchange Rate")
+        .and_then(|x| x.get("5. Exchange Rate"))
+        .and_then(|x| x.as_str())
+        .and_then(|x| x.parse::<f64>().ok());
+
+    if let Some(rate) = rate {
+        return Ok(rate);
+    }
+
+    if let Some(note) = response.get("Note").or_else(|| response.get("Information")) {
+        return Err(StockoError::RateLimited(
+            note.as_str().unwrap_or_default().to_string(),
+        ));
+    }
+
+    Err(StockoError::DeserializationError(format!(
+        "response did not contain an exchange rate for {}",
+        pair
+    )))
+}
+
+fn parse_time_series_response(
+    symbol: &str,
+    interval: Interval,
+    body: &str,
+) -> Result<TimeSeries, StockoError> {
+    let mut response = mapStockoErr!(
+        StockoError::DeserializationError,
+        serde_json::from_str::<serde_json::Value>(body)
+    )?;
+
+    if let Some(entries) = response.get_mut(interval.series_key()) {
+        return mapStockoErr!(
+            StockoError::DeserializationError,
+            serde_json::from_value::<TimeSeries>(entries.take())
+        );
+    }
+
+    // AlphaVantage reports call frequency limits under either key depending on the endpoint
+    if let Some(note) = response.get("Note").or_else(|| response.get("Information")) {
+        return Err(StockoError::RateLimited(
+            note.as_str().unwrap_or_default().to_string(),
+        ));
+    }
+
+    if response.get("Error Message").is_some() {
+        return Err(StockoError::InvalidSymbol {
+            symbol: String::from(symbol),
+        });
+    }
+
+    Err(StockoError::DeserializationError(String::from(
+        "response did not contain any time series entries",
+    )))
+}
+
+/// What the `list` command displays
+struct ListOptions {
+    only: Collection,
+    base_currency: Option<Currency>,
+    archive_limit: Option<usize>,
+    archive_page: usize,
+    sort: SortOrder,
+    offline: bool,
+    /// Symbol to compare the change of each holding against
+    benchmark: Option<String>,
+    method: CostMethod,
+    /// Number of entries back to measure the change from
+    change_days: usize,
+    /// Seconds between redrawing the list, which is only drawn once without it
+    refresh: Option<u64>,
+}
+
+fn list(
+    format: OutputFormat,
+    render: RenderOptions,
+    list_options: ListOptions,
+    options: FetchOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<(), StockoError> {
+    let mut cache = QuoteCache::new(fetcher);
+    cache.lookback = list_options.change_days;
+    cache.interval = options.interval;
+
+    let seconds = match list_options.refresh {
+        Some(seconds) => seconds,
+        None => return list_once(format, render, &list_options, options, &mut cache),
+    };
+
+    unsafe {
+        let handler: extern "C" fn(libc::c_int) = on_interrupt;
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+    refresh_list(
+        format,
+        render,
+        &list_options,
+        options,
+        &mut cache,
+        Duration::from_secs(seconds),
+        Duration::from_secs(MIN_REFRESH_SECS),
+        &|| INTERRUPTED.load(AtomicOrdering::SeqCst),
+    )
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, AtomicOrdering::SeqCst);
+}
+
+/// Redraws the list every `interval`, but no more often than every `minimum`, until `stop`
+/// returns true. Quotes older than the interval are fetched again, and a failed redraw is
+/// reported without stopping the loop.
+#[allow(clippy::too_many_arguments)]
+fn refresh_list(
+    format: OutputFormat,
+    render: RenderOptions,
+    list_options: &ListOptions,
+    options: FetchOptions,
+    cache: &mut QuoteCache,
+    interval: Duration,
+    minimum: Duration,
+    stop: &dyn Fn() -> bool,
+) -> Result<(), StockoError> {
+    let interval = interval.max(minimum);
+
+    while !stop() {
+        let started = Instant::now();
+        cache.expire(interval);
+
+        if format == OutputFormat::Table {
+            print!("\x1b[2J\x1b[H");
+        }
+        if let Err(e) = list_once(format, render, list_options, options, cache) {
+            eprintln!("Error: {}", e);
+        }
+        if format == OutputFormat::Table {
+            println!(
+                "Refreshing every {}s, press Ctrl-C to stop",
+                interval.as_secs()
+            );
+        }
+
+        while started.elapsed() < interval && !stop() {
+            thread::sleep(interval.min(Duration::from_millis(100)));
+        }
+    }
+
+    // Ctrl-C is echoed as ^C, so finish that line before the shell prompt comes back
+    println!();
+    Ok(())
+}
+
+fn list_once(
+    format: OutputFormat,
+    render: RenderOptions,
+    list_options: &ListOptions,
+    options: FetchOptions,
+    cache: &mut QuoteCache,
+) -> Result<(), StockoError> {
+    let only = list_options.only;
+    let offline = list_options.offline;
+    let mut collection = load_data()?;
+    cache.offline = offline;
+
+    if !offline {
+        let mut symbols: Vec<&str> = Vec::new();
+        if only.includes(Collection::Portfolio) {
+            symbols.extend(collection.portfolio.keys().map(|x| x.as_str()));
+        }
+        if only.includes(Collection::Watchlist) {
+            symbols.extend(collection.watchlist.keys().map(|x| x.as_str()));
+        }
+
+        // Only fall back to the stored prices when nothing could be fetched at all, otherwise
+        // the symbols that failed are listed as unavailable
+        if let Err(e) = cache.prefetch(symbols, options.concurrency) {
+            if cache.series.is_empty() {
+                eprintln!("{}. Showing the last known prices instead", e);
+                cache.offline = true;
+            } else {
+                eprintln!("{}. Some prices are unavailable", e);
+            }
+        }
+    }
+
+    let fetched = !cache.series.is_empty();
+    if fetched {
+        collection.remember_quotes(cache);
+    }
+
+    if format == OutputFormat::Json {
+        let report = collection.report(cache, only, list_options.method)?;
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{}", render_list(&collection, cache, render, list_options)?);
+    }
+
+    // The data may have changed while the quotes were fetched, so only the quotes are
+    // stored on top of whatever was saved in the meantime
+    if fetched {
+        let mut latest = load_data()?;
+        latest.remember_quotes(cache);
+        save_data(latest)?;
+    }
+    Ok(())
+}
+
+/// The benchmark is only extra context, so failing to fetch it leaves the column out rather
+/// than failing the whole list
+fn fetch_benchmark(cache: &mut QuoteCache, symbol: &str) -> Option<StockMetrics> {
+    if cache.offline {
+        eprintln!("Leaving out the benchmark while offline");
+        return None;
+    }
+
+    let lookback = cache.lookback;
+    match cache
+        .get(symbol)
+        .and_then(|x| calculate_stock_metrics(symbol, x, lookback))
+    {
+        Ok(metrics) => Some(metrics),
+        Err(e) => {
+            eprintln!("Leaving out the benchmark {}. Cause: {}", symbol, e);
+            None
+        }
+    }
+}
+
+fn render_list(
+    collection: &StockCollections,
+    cache: &mut QuoteCache,
+    render: RenderOptions,
+    list_options: &ListOptions,
+) -> Result<String, StockoError> {
+    let ListOptions {
+        only,
+        base_currency,
+        archive_limit,
+        archive_page,
+        sort,
+        method,
+        ..
+    } = *list_options;
+
+    let mut s = String::new();
+    if only.includes(Collection::Portfolio) {
+        let conversion = match base_currency {
+            Some(base) => {
+                let currencies: Vec<Currency> = collection
+                    .portfolio
+                    .values()
+                    .map(|x| x.currency())
+                    .collect();
+                Some(Conversion::new(base, &currencies, cache.fetcher)?)
+            }
+            None => None,
+        };
+        let benchmark = match list_options.benchmark {
+            Some(ref symbol) => fetch_benchmark(cache, symbol),
+            None => None,
+        };
+        s += &*collection.render_portfolio(
+            cache,
+            render,
+            conversion.as_ref(),
+            sort,
+            benchmark.as_ref(),
+            method,
+        )?;
+    }
+    if only.includes(Collection::Watchlist) {
+        s += &*collection.render_watch_list(cache, render, sort)?;
+    }
+    if only.includes(Collection::Archive) {
+        s += &*collection.render_archive(render, archive_limit, archive_page, method)?;
+    }
+
+    if cache.offline {
+        if let Some(fetched_at) = collection.stored_quotes_fetched_at() {
+            s += &*format!("Offline, showing prices stored {} (stale)\n", fetched_at);
+        }
+    } else if let Some(fetched_at) = cache.fetched_at() {
+        s += &*format!(
+            "Prices as of {} (delayed)\n",
+            fetched_at.format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(s)
+}
+
+fn summary(
+    render: RenderOptions,
+    base_currency: Option<Currency>,
+    method: CostMethod,
+    options: FetchOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<(), StockoError> {
+    let collection = load_data()?;
+    let mut cache = QuoteCache::new(fetcher);
+    // The holdings that failed are left out of the totals, with a warning for each
+    if let Err(e) = cache.prefetch(
+        collection.portfolio.keys().map(|x| x.as_str()),
+        options.concurrency,
+    ) {
+        eprintln!("{}. Some prices are unavailable", e);
+    }
+
+    let conversion = match base_currency {
+        Some(base) => {
+            let currencies: Vec<Currency> = collection
+                .portfolio
+                .values()
+                .chain(collection.archive.values())
+                .map(|x| x.currency())
+                .collect();
+            Some(Conversion::new(base, &currencies, cache.fetcher)?)
+        }
+        None => None,
+    };
+    print!(
+        "{}",
+        collection.render_summary(&mut cache, render, conversion.as_ref(), method)?
+    );
+    Ok(())
+}
+
+/// Stores the collections as the selected account's, see `save_data_file` for the write itself
+fn save_data(collections: StockCollections) -> Result<(), StockoError> {
+    save_account(selected_account(), collections)
+}
+
+/// Loads the collections of the selected account, which are empty for a new account
+fn load_data() -> Result<StockCollections, StockoError> {
+    load_account(selected_account())
+}
+
+/// Replaces the collections of `account`, leaving every other account as it was
+fn save_account(account: &str, collections: StockCollections) -> Result<(), StockoError> {
+    let mut data = load_data_file()?;
+    data.accounts.insert(String::from(account), collections);
+    save_data_file(&data)
+}
+
+fn load_account(account: &str) -> Result<StockCollections, StockoError> {
+    let mut data = load_data_file()?;
+    Ok(data
+        .accounts
+        .remove(account)
+        .unwrap_or_else(StockCollections::new))
+}
+
+fn selected_account() -> &'static str {
+    ACCOUNT.get().map_or(DEFAULT_ACCOUNT, |x| x.as_str())
+}
+
+/// Writes to a temporary file next to the data file and renames it into place, so an
+/// interrupted save leaves the previous data intact
+fn save_data_file(data: &DataFile) -> Result<(), StockoError> {
+    let json = mapStockoErr!(StockoError::SaveDataError, serde_json::to_vec(data))?;
+
+    let path = get_data_file_path();
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = mapStockoErr!(
+        StockoError::SaveDataError,
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+    )?;
+
+    mapStockoErr!(StockoError::SaveDataError, file.write_all(&*json))?;
+    mapStockoErr!(StockoError::SaveDataError, file.sync_all())?;
+
+    mapStockoErr!(StockoError::SaveDataError, fs::rename(&tmp_path, &path))
+}
+
+fn load_data_file() -> Result<DataFile, StockoError> {
+    let path = get_data_file_path();
+
+    if !path.exists() {
+        return Ok(DataFile::default());
+    }
+
+    let mut file = mapStockoErr!(StockoError::ReadDataError, File::open(path))?;
+
+    let mut buf = String::new();
+    mapStockoErr!(StockoError::ReadDataError, file.read_to_string(&mut buf))?;
+
+    if buf.trim().is_empty() {
+        return Ok(DataFile::default());
+    }
+
+    let value = mapStockoErr!(
+        StockoError::ReadDataError,
+        serde_json::from_str::<serde_json::Value>(buf.as_str())
+    )?;
+
+    // Older files are only upgraded in memory, the new layout is written by the next save
+    let version = data_version(&value);
+    mapStockoErr!(
+        StockoError::ReadDataError,
+        serde_json::from_value::<DataFile>(migrate(value, version)?)
+    )
+}
+
+/// Files from before the version was written are told apart by their layout
+fn data_version(value: &serde_json::Value) -> u32 {
+    match value.get("version").and_then(|x| x.as_u64()) {
+        Some(version) => version as u32,
+        None if value.get("accounts").is_some() => 1,
+        None => 0,
+    }
+}
+
+/// Upgrades the layout of `data` from `from_version` to `DATA_VERSION` one version at a time
+fn migrate(
+    mut data: serde_json::Value,
+    from_version: u32,
+) -> Result<serde_json::Value, StockoError> {
+    if from_version > DATA_VERSION {
+        return Err(StockoError::UnsupportedDataVersion(from_version));
+    }
+
+    for version in from_version..DATA_VERSION {
+        data = match version {
+            // Files from before accounts hold a single set of collections, which becomes the
+            // default account
+            0 => {
+                let mut accounts = serde_json::Map::new();
+                accounts.insert(String::from(DEFAULT_ACCOUNT), data);
+                let mut upgraded = serde_json::Map::new();
+                upgraded.insert(String::from("accounts"), accounts.into());
+                upgraded.into()
+            }
+            _ => unreachable!("no migration from version {}", version),
+        };
+    }
+
+    data["version"] = DATA_VERSION.into();
+    Ok(data)
+}
+
+fn accounts(format: OutputFormat) -> Result<(), StockoError> {
+    let data = load_data_file()?;
+
+    let mut names: Vec<&str> = data.accounts.keys().map(|x| x.as_str()).collect();
+    if !names.contains(&selected_account()) {
+        names.push(selected_account());
+    }
+    names.sort();
+
+    let empty = StockCollections::new();
+    let summaries: Vec<AccountSummary> = names
+        .into_iter()
+        .map(|name| {
+            let collections = data.accounts.get(name).unwrap_or(&empty);
+            AccountSummary {
+                name,
+                portfolio: collections.portfolio.len(),
+                watchlist: collections.watchlist.len(),
+                archive: collections.archive.len(),
+            }
+        })
+        .collect();
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&summaries).unwrap());
+        return Ok(());
+    }
+
+    for summary in summaries {
+        let marker = if summary.name == selected_account() {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{} {}  {} held, {} watched, {} archived",
+            marker, summary.name, summary.portfolio, summary.watchlist, summary.archive
+        );
+    }
+    Ok(())
+}
+
+fn get_api_key() -> Result<String, StockoError> {
+    if let Ok(key) = std::env::var(API_KEY_VAR) {
+        if !key.is_empty() {
+            return Ok(key);
+        }
+    }
+
+    match load_config()?.api_key {
+        Some(ref key) if !key.is_empty() => Ok(key.clone()),
+        _ => Err(StockoError::MissingApiKey),
+    }
+}
+
+fn load_config() -> Result<Config, StockoError> {
+    let path = get_config_file_path();
+
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let mut file = mapStockoErr!(StockoError::ReadConfigError, File::open(path))?;
+
+    let mut buf = String::new();
+    mapStockoErr!(StockoError::ReadConfigError, file.read_to_string(&mut buf))?;
+
+    mapStockoErr!(
+        StockoError::ReadConfigError,
+        serde_json::from_str::<Config>(buf.as_str())
+    )
+}
+
+fn today() -> String {
+    Local::today().format("%Y-%m-%d").to_string()
+}
+
+fn get_data_file_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(DATA_PATH_VAR).filter(|x| !x.is_empty()) {
+        return PathBuf::from(path);
+    }
+
+    let legacy_path = get_exe_dir().join(DATA_FILE_NAME);
+
+    match get_config_dir() {
+        Some(dir) => {
+            let path = dir.join(DATA_FILE_NAME);
+            // Older versions kept the data next to the executable, carry it over on first use
+            if !path.exists() && legacy_path.exists() {
+                let _ = fs::copy(&legacy_path, &path);
+            }
+            path
+        }
+        None => legacy_path,
+    }
+}
+
+fn get_config_file_path() -> PathBuf {
+    get_config_dir()
+        .unwrap_or_else(get_exe_dir)
+        .join(CONFIG_FILE_NAME)
+}
+
+/// Platform config directory for stocko, created if it doesn't exist yet
+fn get_config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+
+    let dir = base.join("stocko");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+fn get_exe_dir() -> PathBuf {
+    let mut exe_path = std::env::current_exe().unwrap();
+    exe_path.pop();
+    exe_path
+}
+
+/// Uppercases the symbol and adds the suffix for the exchange if it doesn't have it already,
+/// so `shop -e tsx`, `SHOP -e tsx` and `SHOP.TO -e tsx` are all stored as SHOP.TO
+fn normalize_symbol(raw: &str, exchange_symbol: Option<&str>) -> Result<String, StockoError> {
+    let mut symbol = raw.trim().to_uppercase();
+    if let Some(exchange_symbol) = exchange_symbol {
+        let suffix = suffix_for_exchange_symbol(exchange_symbol)?;
+        if !symbol.ends_with(suffix) {
+            symbol.push_str(suffix);
+        }
+    }
+    Ok(symbol)
+}
+
+/// The normalized symbol from the `symbol` and `exchange` arguments of a subcommand
+fn symbol_arg(matches: &ArgMatches) -> Result<String, StockoError> {
+    normalize_symbol(
+        matches.value_of("symbol").unwrap(),
+        matches.value_of("exchange"),
+    )
+}
+
+fn suffix_for_exchange_symbol(exchange_symbol: &str) -> Result<&'static str, StockoError> {
+    Exchange::from_symbol(Some(exchange_symbol)).map(|x| x.suffix())
+}
+
+/// Measures the change from the close `lookback` entries before the latest one, or from the
+/// oldest close when the series is not that long
+fn calculate_stock_metrics(
+    symbol: &str,
+    time_series: &TimeSeries,
+    lookback: usize,
+) -> Result<StockMetrics, StockoError> {
+    let entries = time_series.entries();
+    let num_entries = entries.len();
+
+    if num_entries == 0 {
+        return Err(StockoError::InsufficientData {
+            symbol: String::from(symbol),
+        });
+    }
+
+    // A newly listed symbol may only have a single entry, which is treated as unchanged
+    let period = lookback.max(1).min(num_entries - 1);
+    let (_date_yesterday, entry_yesterday) = entries[num_entries - 1 - period];
+    let (date_today, entry_today) = entries[num_entries - 1];
+
+    let change_value = entry_today.close - entry_yesterday.close;
+    let change_percentage = 100.0 * fraction_of(change_value, entry_yesterday.close);
+
+    Ok(StockMetrics {
+        change_percentage: change_percentage,
+        change: change_value,
+        close_today: entry_today.close,
+        close_yesterday: entry_yesterday.close,
+        date: date_today.clone(),
+        period,
+    })
+}
+
+/// `amount` as a fraction of `total`, or 0 when there is no total to compare against
+fn fraction_of(amount: f64, total: f64) -> f64 {
+    if total.abs() > f64::EPSILON {
+        amount / total
+    } else {
+        0.0
+    }
+}
+
+/// The latest close along with the date it is from, which can lag behind today
+fn generate_price_string(metrics: &StockMetrics, render: RenderOptions) -> String {
+    format!("{} ({})", render.number(metrics.close_today), metrics.date)
+}
+
+fn change_header(lookback: usize, interval: Interval) -> String {
+    if lookback > 1 {
+        format!("Change ({}{})", lookback, interval.unit())
+    } else {
+        String::from("Change")
+    }
+}
+
+/// The change, noting the period it covers when the series was too short for the lookback
+fn generate_period_change_string(
+    metrics: &StockMetrics,
+    lookback: usize,
+    interval: Interval,
+    render: RenderOptions,
+) -> String {
+    let change = generate_change_string(metrics, render);
+    if metrics.period < lookback {
+        format!("{} over {}{}", change, metrics.period, interval.unit())
+    } else {
+        change
+    }
+}
+
+fn generate_change_string(metrics: &StockMetrics, render: RenderOptions) -> String {
+    return if metrics.change >= 0.0 {
+        render.paint(
+            Green,
+            format!(
+                "+{} (+{:.2}%)",
+                render.number(metrics.change),
+                metrics.change_percentage
+            ),
+        )
+    } else {
+        render.paint(
+            Red,
+            format!(
+                "{} ({:.2}%)",
+                render.number(metrics.change),
+                metrics.change_percentage
+            ),
+        )
+    };
+}
+
+fn generate_gain_string(gain: f64, gain_percentage: f64, render: RenderOptions) -> String {
+    return if gain >= 0.0 {
+        render.paint(
+            Green,
+            format!(
+                "+{} (+{:.2}%)",
+                render.number(gain),
+                gain_percentage * 100.0
+            ),
+        )
+    } else {
+        render.paint(
+            Red,
+            format!("{} ({:.2}%)", render.number(gain), gain_percentage * 100.0),
+        )
+    };
+}
+
+/// Percentage points a change is above or below the benchmark's
+fn generate_benchmark_string(difference: f64, render: RenderOptions) -> String {
+    if difference >= 0.0 {
+        render.paint(Green, format!("+{:.2}%", difference))
+    } else {
+        render.paint(Red, format!("{:.2}%", difference))
+    }
+}
+
+/// Shares aren't rounded to `--precision`, only the noise below `SHARE_EPSILON` is left out
+fn generate_shares_string(shares: f64) -> String {
+    let shares = format!("{:.6}", shares);
+    String::from(shares.trim_end_matches('0').trim_end_matches('.'))
+}
+
+/// Share of the portfolio's value, given as a fraction, or blank when there is nothing to weigh
+/// it against
+fn generate_weight_string(weight: Option<f64>) -> String {
+    match weight {
+        Some(weight) => format!("{:.2}%", weight * 100.0),
+        None => String::new(),
+    }
+}
+
+fn generate_amount_string(amount: f64, render: RenderOptions) -> String {
+    if amount >= 0.0 {
+        render.paint(Green, format!("+{}", render.number(amount)))
+    } else {
+        render.paint(Red, render.number(amount))
+    }
+}
+
+fn recent_closes(time_series: &TimeSeries, count: usize) -> Vec<f64> {
+    let entries = time_series.entries();
+    let skip = entries.len().saturating_sub(count);
+    entries
+        .into_iter()
+        .skip(skip)
+        .map(|(_date, entry)| entry.close)
+        .collect()
+}
+
+fn sparkline(closes: &[f64]) -> String {
+    let min = closes.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = closes.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    let top = (SPARKLINE_CHARS.len() - 1) as f64;
+
+    closes
+        .iter()
+        .map(|close| {
+            // A flat series has no range to scale against so draw it through the middle
+            let level = if range > 0.0 {
+                ((close - min) / range * top).round()
+            } else {
+                (top / 2.0).floor()
+            };
+            SPARKLINE_CHARS[level as usize]
+        })
+        .collect()
+}
+
+fn generate_trend_string(closes: &[f64], render: RenderOptions) -> String {
+    let line = sparkline(closes);
+    match (closes.first(), closes.last()) {
+        (Some(first), Some(last)) if last >= first => render.paint(Green, line),
+        (Some(_), Some(_)) => render.paint(Red, line),
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn order(shares: f64, share_price: f64, date: &str) -> Order {
+        Order {
+            shares,
+            share_price,
+            date: Some(String::from(date)),
+            fee: 0.0,
+        }
+    }
+
+    fn stock_with(orders: Vec<Order>) -> Stock {
+        Stock {
+            symbol: String::from("AAPL"),
+            orders,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn cost_methods_differ_on_partial_sell() {
+        // Buys at 10 and 20, then a sell at 30 that empties the first lot and half the second
+        let stock = stock_with(vec![
+            order(10.0, 10.0, "2024-01-01"),
+            order(10.0, 20.0, "2024-02-01"),
+            order(-15.0, 30.0, "2024-03-01"),
+        ]);
+
+        let (book_cost, realized) = stock.average_cost_basis();
+        assert_close(book_cost, 75.0);
+        assert_close(realized, 225.0);
+
+        let (book_cost, realized) = stock.lot_cost_basis(CostMethod::Fifo);
+        assert_close(book_cost, 100.0);
+        assert_close(realized, 250.0);
+
+        let (book_cost, realized) = stock.lot_cost_basis(CostMethod::Lifo);
+        assert_close(book_cost, 50.0);
+        assert_close(realized, 200.0);
+    }
+
+    #[test]
+    fn lots_are_matched_by_date_not_insertion_order() {
+        let stock = stock_with(vec![
+            order(-5.0, 30.0, "2024-03-01"),
+            order(10.0, 20.0, "2024-02-01"),
+            order(10.0, 10.0, "2024-01-01"),
+        ]);
+
+        let (book_cost, realized) = stock.lot_cost_basis(CostMethod::Fifo);
+        assert_close(book_cost, 250.0);
+        assert_close(realized, 100.0);
+    }
+
+    #[test]
+    fn split_keeps_cost_basis() {
+        // A forward split, a reverse split rounded down and one leaving a fraction of a share
+        for &(num, den, shares, expected) in
+            &[(2, 1, 10.0, 20.0), (1, 10, 25.0, 2.0), (3, 2, 5.0, 7.0)]
+        {
+            let mut stock = stock_with(vec![order(shares, 100.0 / shares, "2024-01-01")]);
+            stock.apply_split(num, den, Rounding::Floor);
+
+            let metrics = stock.calculate_order_metrics();
+            assert_close(metrics.total_shares, expected);
+            assert_close(metrics.book_cost, 100.0);
+            assert_close(metrics.average_cost, 100.0 / expected);
+        }
+    }
+
+    #[test]
+    fn split_rounding_modes() {
+        // 25 shares split 1:10 come out to 2.5
+        let cases = [
+            (Rounding::Floor, 2.0, 0.5),
+            (Rounding::Nearest, 3.0, 0.0),
+            (Rounding::KeepFractional, 2.5, 0.0),
+        ];
+        for &(rounding, shares, cash_in_lieu) in &cases {
+            let mut stock = stock_with(vec![order(25.0, 4.0, "2024-01-01")]);
+            stock.apply_split(1, 10, rounding);
+
+            assert_close(stock.calculate_order_metrics().total_shares, shares);
+            let split = stock.splits.last().unwrap();
+            assert_eq!(split.rounding, rounding);
+            assert_close(split.cash_in_lieu_shares, cash_in_lieu);
+        }
+    }
+
+    #[test]
+    fn nearest_rounding_down_pays_cash_in_lieu() {
+        let mut stock = stock_with(vec![order(24.0, 4.0, "2024-01-01")]);
+        stock.apply_split(1, 10, Rounding::Nearest);
+
+        assert_close(stock.calculate_order_metrics().total_shares, 2.0);
+        assert_close(stock.splits[0].cash_in_lieu_shares, 0.4);
+    }
+
+    #[test]
+    fn exchange_names_ignore_case() {
+        let exchange = Exchange::from_symbol(Some("nyse")).unwrap();
+        assert_eq!(exchange, Exchange::NYSE);
+        assert_eq!(exchange.suffix(), "");
+
+        assert_eq!(Exchange::from_symbol(None).unwrap(), Exchange::NYSE);
+        assert!(matches!(
+            Exchange::from_symbol(Some("nsye")),
+            Err(StockoError::InvalidExchange)
+        ));
+    }
+
+    #[test]
+    fn fractional_orders() {
+        let mut collection = StockCollections::new();
+        for &shares in &[0.5, 0.25, -0.3] {
+            let order = order(shares, 100.0, "2024-01-01");
+            collection
+                .apply_order(String::from("AAPL"), Exchange::NASDAQ, order)
+                .unwrap();
+        }
+
+        let stock = &collection.portfolio["AAPL"];
+        assert_close(stock.calculate_order_metrics().total_shares, 0.45);
+        assert!(collection.archive.is_empty());
+    }
+
+    #[test]
+    fn selling_within_epsilon_of_the_position_archives_it() {
+        // 0.1 + 0.2 doesn't add up to exactly 0.3 as a float
+        let mut collection = StockCollections::new();
+        for &shares in &[0.1, 0.2] {
+            let order = order(shares, 100.0, "2024-01-01");
+            collection
+                .apply_order(String::from("AAPL"), Exchange::NASDAQ, order)
+                .unwrap();
+        }
+
+        assert!(collection.closes_position("AAPL", -0.3));
+        let sell = order(-0.3, 120.0, "2024-02-01");
+        collection
+            .apply_order(String::from("AAPL"), Exchange::NASDAQ, sell)
+            .unwrap();
+
+        assert!(collection.portfolio.is_empty());
+        let archived = &collection.archive["AAPL"];
+        assert_eq!(archived.archived_on.as_deref(), Some("2024-02-01"));
+    }
+
+    /// Series with a close on each of the given days
+    fn time_series(closes: &[(&str, f64)]) -> TimeSeries {
+        let mut entries = serde_json::Map::new();
+        for &(date, close) in closes {
+            let mut entry = serde_json::Map::new();
+            for field in &["1. open", "2. high", "3. low", "4. close"] {
+                entry.insert(field.to_string(), close.to_string().into());
+            }
+            entry.insert(String::from("5. volume"), "1000".into());
+            entries.insert(String::from(date), serde_json::Value::Object(entry));
+        }
+        serde_json::from_value(serde_json::Value::Object(entries)).unwrap()
+    }
+
+    #[test]
+    fn single_entry_series_is_unchanged() {
+        let series = time_series(&[("2024-01-02", 42.0)]);
+        let metrics = calculate_stock_metrics("NEWCO", &series, 1).unwrap();
+
+        assert_close(metrics.close_today, 42.0);
+        assert_close(metrics.change, 0.0);
+        assert_close(metrics.change_percentage, 0.0);
+        assert_eq!(metrics.period, 0);
+    }
+
+    #[test]
+    fn empty_series_is_insufficient_data() {
+        let series = time_series(&[]);
+        assert!(matches!(
+            calculate_stock_metrics("NEWCO", &series, 1),
+            Err(StockoError::InsufficientData { ref symbol }) if symbol == "NEWCO"
+        ));
+    }
+
+    #[test]
+    fn plain_output_has_no_escape_codes() {
+        let series = time_series(&[("2024-01-01", 10.0), ("2024-01-02", 12.0)]);
+        let metrics = calculate_stock_metrics("AAPL", &series, 1).unwrap();
+        let render = |color| RenderOptions {
+            color,
+            plain: true,
+            precision: DEFAULT_PRECISION,
+        };
+
+        let cells = |render: RenderOptions| {
+            vec![
+                render.bold(String::from("AAPL")),
+                generate_change_string(&metrics, render),
+                generate_gain_string(-5.0, -0.25, render),
+                generate_trend_string(&[10.0, 12.0], render),
+            ]
+        };
+
+        let coloured = cells(render(ColorMode::Enabled));
+        assert!(coloured.iter().all(|x| x.contains('\u{1b}')));
+
+        let plain = cells(render(ColorMode::Disabled));
+        let mut table = Table::new();
+        let row = plain.iter().map(|x| Cell::new(x.clone(), 1)).collect();
+        table.add_row(Row::new(row));
+        let output = render(ColorMode::Disabled).table(table);
+        assert!(!output.contains('\u{1b}'));
+        assert_eq!(output.split('\t').count(), plain.len());
+    }
+
+    /// Two days of closes, rising from 10 to 11
+    const RISING: [(&str, f64); 2] = [("2024-01-01", 10.0), ("2024-01-02", 11.0)];
+
+    /// Stands in for AlphaVantage, answering from canned closes, listings and rates. It can also
+    /// be made slow or rate limited, and records every symbol it is asked for.
+    #[derive(Default)]
+    struct StubFetcher {
+        closes: HashMap<String, Vec<(&'static str, f64)>>,
+        listings: Vec<&'static str>,
+        rates: HashMap<(Currency, Currency), f64>,
+        /// Number of fetches answered as rate limited before any succeeds
+        failures: u32,
+        /// How long each fetch takes
+        delay: Duration,
+        fetched: Mutex<Vec<String>>,
+    }
+
+    impl StubFetcher {
+        fn with_closes(mut self, symbol: &str, closes: &[(&'static str, f64)]) -> StubFetcher {
+            self.closes.insert(String::from(symbol), closes.to_vec());
+            self
+        }
+
+        /// Every symbol fetched so far, in the order asked for
+        fn fetched(&self) -> Vec<String> {
+            self.fetched.lock().unwrap().clone()
+        }
+    }
+
+    impl Fetcher for StubFetcher {
+        fn fetch(&self, symbol: &str) -> Result<TimeSeries, StockoError> {
+            thread::sleep(self.delay);
+            let attempt = {
+                let mut fetched = self.fetched.lock().unwrap();
+                fetched.push(String::from(symbol));
+                fetched.len() as u32
+            };
+            if attempt <= self.failures {
+                return Err(StockoError::RateLimited(String::from("slow down")));
+            }
+
+            match self.closes.get(symbol) {
+                Some(closes) => Ok(time_series(closes)),
+                None => Err(StockoError::InvalidSymbol {
+                    symbol: String::from(symbol),
+                }),
+            }
+        }
+
+        fn search(&self, keywords: &str) -> Result<Vec<SearchMatch>, StockoError> {
+            let keywords = keywords.to_uppercase();
+            let base = keywords.split('.').next().unwrap_or_default();
+            let listings = self.listings.iter().filter(|x| x.starts_with(base));
+            Ok(listings
+                .map(|&symbol| SearchMatch {
+                    symbol: String::from(symbol),
+                    name: String::new(),
+                    region: String::new(),
+                    currency: String::new(),
+                })
+                .collect())
+        }
+
+        fn fx_rate(&self, from: Currency, to: Currency) -> Result<f64, StockoError> {
+            self.rates
+                .get(&(from, to))
+                .cloned()
+                .ok_or_else(|| StockoError::InvalidCurrency(String::from(from.code())))
+        }
+    }
+
+    #[test]
+    fn prefetch_requests_each_symbol_once() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &RISING)
+            .with_closes("MSFT", &RISING);
+        let mut cache = QuoteCache::new(&fetcher);
+
+        cache.prefetch(vec!["AAPL", "MSFT", "AAPL"], 4).unwrap();
+        cache.get("AAPL").unwrap();
+        cache.prefetch(vec!["MSFT"], 4).unwrap();
+
+        let mut fetched = fetcher.fetched();
+        fetched.sort();
+        assert_eq!(fetched, vec!["AAPL", "MSFT"]);
+    }
+
+    #[test]
+    fn retries_until_the_request_succeeds() {
+        let fetcher = StubFetcher {
+            failures: 2,
+            ..Default::default()
+        }
+        .with_closes("AAPL", &RISING);
+
+        let series = retry_with_backoff(3, Duration::ZERO, || fetcher.fetch("AAPL")).unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(fetcher.fetched().len(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let fetcher = StubFetcher {
+            failures: 2,
+            ..Default::default()
+        }
+        .with_closes("AAPL", &RISING);
+
+        let result = retry_with_backoff(1, Duration::ZERO, || fetcher.fetch("AAPL"));
+        assert!(matches!(result, Err(StockoError::RateLimited(_))));
+        assert_eq!(fetcher.fetched().len(), 2);
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let mut attempts = 0;
+        let result: Result<(), StockoError> = retry_with_backoff(3, Duration::ZERO, || {
+            attempts += 1;
+            Err(StockoError::MissingApiKey)
+        });
+        assert!(matches!(result, Err(StockoError::MissingApiKey)));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn selling_keeps_the_average_cost() {
+        let stock = stock_with(vec![
+            order(10.0, 10.0, "2024-01-01"),
+            order(10.0, 20.0, "2024-02-01"),
+            order(-5.0, 25.0, "2024-03-01"),
+        ]);
+
+        let metrics = stock.calculate_order_metrics();
+        assert_close(metrics.total_shares, 15.0);
+        assert_close(metrics.average_cost, 15.0);
+        assert_close(metrics.book_cost, 225.0);
+        assert_close(metrics.realized_gain, 50.0);
+    }
+
+    /// STOCKO_DATA is shared by every test in the process, so tests using it take turns
+    static DATA_PATH_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Runs `f` with STOCKO_DATA pointing at a data file in a fresh temporary directory
+    fn with_data_path<T, F: FnOnce(&Path) -> T>(name: &str, f: F) -> T {
+        let _guard = DATA_PATH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("stocko-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(DATA_FILE_NAME);
+
+        std::env::set_var(DATA_PATH_VAR, &path);
+        let result = f(&path);
+        std::env::remove_var(DATA_PATH_VAR);
+
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn data_file_holding(symbol: &str) -> DataFile {
+        let mut collections = StockCollections::new();
+        let buy = order(1.0, 10.0, "2024-01-01");
+        collections
+            .apply_order(String::from(symbol), Exchange::NYSE, buy)
+            .unwrap();
+
+        let mut data = DataFile::default();
+        data.accounts
+            .insert(String::from(DEFAULT_ACCOUNT), collections);
+        data
+    }
+
+    #[test]
+    fn failed_save_leaves_the_file_intact() {
+        with_data_path("failed-save", |path| {
+            save_data_file(&data_file_holding("AAPL")).unwrap();
+            let original = fs::read(path).unwrap();
+
+            // The temporary file can't be created when a directory is in its place
+            let mut tmp_path = path.as_os_str().to_owned();
+            tmp_path.push(".tmp");
+            fs::create_dir(&tmp_path).unwrap();
+
+            let result = save_data_file(&data_file_holding("MSFT"));
+            assert!(matches!(result, Err(StockoError::SaveDataError(_))));
+            assert_eq!(fs::read(path).unwrap(), original);
+
+            let loaded = load_data_file().unwrap();
+            let portfolio = &loaded.accounts[DEFAULT_ACCOUNT].portfolio;
+            assert!(portfolio.contains_key("AAPL"));
+            assert!(!portfolio.contains_key("MSFT"));
+        });
+    }
+
+    #[test]
+    fn empty_data_file_loads_as_empty_collections() {
+        with_data_path("empty-file", |path| {
+            fs::write(path, "  \n").unwrap();
+
+            let data = load_data_file().unwrap();
+            assert_eq!(data.version, DATA_VERSION);
+            assert!(data.accounts.is_empty());
+
+            let collections = load_data().unwrap();
+            assert_eq!(collections.count(Collection::All), 0);
+        });
+    }
+
+    #[test]
+    fn malformed_data_file_is_a_read_error() {
+        with_data_path("malformed-file", |path| {
+            fs::write(path, "{\"portfolio\": [").unwrap();
+            assert!(matches!(
+                load_data_file(),
+                Err(StockoError::ReadDataError(_))
+            ));
+
+            // Not UTF-8, which can't be read into a string at all
+            fs::write(path, [0xff, 0xfe, 0x00]).unwrap();
+            assert!(matches!(
+                load_data_file(),
+                Err(StockoError::ReadDataError(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn prefetch_fetches_concurrently() {
+        let delay = Duration::from_millis(100);
+        let symbols: Vec<String> = (0..10).map(|x| format!("SYM{}", x)).collect();
+        let fetcher = symbols.iter().fold(
+            StubFetcher {
+                delay,
+                ..Default::default()
+            },
+            |fetcher, symbol| fetcher.with_closes(symbol, &RISING),
+        );
+        let mut cache = QuoteCache::new(&fetcher);
+
+        let started = Instant::now();
+        cache
+            .prefetch(symbols.iter().map(|x| x.as_str()), DEFAULT_CONCURRENCY)
+            .unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(cache.series.len(), symbols.len());
+        // Five at a time should take about two delays, well short of one per symbol
+        assert!(
+            elapsed < delay * symbols.len() as u32 / 2,
+            "took {:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn stub_fetcher_answers_in_place_of_alphavantage() {
+        let mut fetcher = StubFetcher::default()
+            .with_closes("SHOP.TO", &[("2024-01-01", 80.0), ("2024-01-02", 100.0)]);
+        fetcher.listings = vec!["SHOP.TRT", "SHOPF"];
+        fetcher.rates.insert((Currency::CAD, Currency::USD), 0.75);
+
+        // The search lists Toronto symbols with its own suffix
+        assert!(validate_symbol("SHOP.TO", &fetcher).is_ok());
+        assert!(matches!(
+            validate_symbol("SHOP.V", &fetcher),
+            Err(StockoError::UnknownSymbol { .. })
+        ));
+
+        let conversion = Conversion::new(Currency::USD, &[Currency::CAD], &fetcher).unwrap();
+        assert_close(conversion.convert(100.0, Currency::CAD), 75.0);
+
+        let mut cache = QuoteCache::new(&fetcher);
+        let stock = Stock {
+            symbol: String::from("SHOP.TO"),
+            exchange: Exchange::TSX,
+            ..Default::default()
+        };
+        let metrics = cache.metrics(&stock).unwrap();
+        assert_close(metrics.close_today, 100.0);
+        assert_close(metrics.change_percentage, 25.0);
+    }
+
+    #[test]
+    fn orders_need_shares_and_a_positive_price() {
+        assert!(matches!(
+            validate_order("AAPL", 0.0, 10.0),
+            Err(StockoError::ZeroShareOrder { .. })
+        ));
+        assert!(matches!(
+            validate_order("AAPL", 1.0, -10.0),
+            Err(StockoError::InvalidPrice(_))
+        ));
+        assert!(matches!(
+            validate_order("AAPL", 1.0, 0.0),
+            Err(StockoError::InvalidPrice(_))
+        ));
+        assert!(validate_order("AAPL", -1.0, 10.0).is_ok());
+    }
+
+    #[test]
+    fn average_cost_without_shares_is_finite() {
+        let metrics = stock_with(Vec::new()).calculate_order_metrics();
+        assert_close(metrics.total_shares, 0.0);
+        assert_close(metrics.average_cost, 0.0);
+
+        // A closed position reports what was paid on average for the shares it bought
+        let closed = stock_with(vec![
+            order(2.0, 10.0, "2024-01-01"),
+            order(2.0, 20.0, "2024-02-01"),
+            order(-4.0, 30.0, "2024-03-01"),
+        ]);
+        let metrics = closed.calculate_order_metrics();
+        assert_close(metrics.total_shares, 0.0);
+        assert_close(metrics.average_cost, 15.0);
+        assert!(metrics.unrealized_gain(30.0).0.is_finite());
+    }
+
+    #[test]
+    fn watch_and_buy_store_the_same_key() {
+        assert_eq!(normalize_symbol("aapl", Some("tsx")).unwrap(), "AAPL.TO");
+        assert_eq!(normalize_symbol("AAPL.TO", Some("TSX")).unwrap(), "AAPL.TO");
+        assert_eq!(normalize_symbol("aapl", None).unwrap(), "AAPL");
+
+        let mut fetcher = StubFetcher::default().with_closes("AAPL.TO", &RISING);
+        fetcher.listings = vec!["AAPL.TRT"];
+
+        with_data_path("watch-then-buy", |_| {
+            let watched = normalize_symbol("AAPL", Some("tsx")).unwrap();
+            watch(watched, Some("tsx"), &fetcher).unwrap();
+
+            let bought = normalize_symbol("aapl", Some("tsx")).unwrap();
+            let buy = order(1.0, 11.0, "2024-01-02");
+            process_order(bought, Some("tsx"), buy, &fetcher, true, &mut ask_terminal).unwrap();
+
+            let collections = load_data().unwrap();
+            let watched: Vec<&String> = collections.watchlist.keys().collect();
+            let bought: Vec<&String> = collections.portfolio.keys().collect();
+            assert_eq!(watched, vec!["AAPL.TO"]);
+            assert_eq!(bought, watched);
+            assert_eq!(collections.portfolio["AAPL.TO"].symbol, "AAPL.TO");
+        });
+    }
+
+    #[test]
+    fn every_exchange_parses_and_has_its_suffix() {
+        let expected = [
+            ("tsx", Exchange::TSX, ".TO"),
+            ("tsxv", Exchange::TSXV, ".V"),
+            ("nyse", Exchange::NYSE, ""),
+            ("nasdaq", Exchange::NASDAQ, ""),
+            ("lse", Exchange::LSE, ".L"),
+            ("asx", Exchange::ASX, ".AX"),
+        ];
+        assert_eq!(expected.len(), EXCHANGES.len());
+
+        for &(name, ref exchange, suffix) in &expected {
+            for name in &[String::from(name), name.to_uppercase()] {
+                assert_eq!(Exchange::from_symbol(Some(name)).unwrap(), *exchange);
+                assert_eq!(suffix_for_exchange_symbol(name).unwrap(), suffix);
+            }
+            assert_eq!(exchange.suffix(), suffix);
+            assert!(exchange.name().eq_ignore_ascii_case(name));
+            assert_eq!(
+                normalize_symbol("abc", Some(name)).unwrap(),
+                format!("ABC{}", suffix)
+            );
+        }
+    }
+
+    /// A data file from before versions and accounts, with orders from before dates and fees
+    const V0_FIXTURE: &str = r#"{
+        "portfolio": {
+            "RY.TO": {
+                "symbol": "RY.TO",
+                "exchange": "TSX",
+                "orders": [{"shares": 10.0, "share_price": 100.0}]
+            }
+        },
+        "watchlist": {
+            "MSFT": {"symbol": "MSFT", "exchange": "NYSE", "orders": []}
+        },
+        "archive": {}
+    }"#;
+
+    #[test]
+    fn v0_data_file_is_upgraded_into_the_default_account() {
+        with_data_path("v0-upgrade", |path| {
+            fs::write(path, V0_FIXTURE).unwrap();
+
+            let data = load_data_file().unwrap();
+            assert_eq!(data.version, DATA_VERSION);
+            assert_eq!(data.accounts.len(), 1);
+            let collections = &data.accounts[DEFAULT_ACCOUNT];
+            let stock = &collections.portfolio["RY.TO"];
+            assert_eq!(stock.currency(), Currency::CAD);
+            assert_eq!(stock.orders[0].date, None);
+            assert!(collections.watchlist.contains_key("MSFT"));
+
+            // Loading alone leaves the old file for the next save to replace
+            assert_eq!(fs::read_to_string(path).unwrap(), V0_FIXTURE);
+
+            save_data_file(&data).unwrap();
+            let saved: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+            assert_eq!(data_version(&saved), DATA_VERSION);
+
+            let reloaded = load_data_file().unwrap();
+            let stock = &reloaded.accounts[DEFAULT_ACCOUNT].portfolio["RY.TO"];
+            assert_close(stock.calculate_order_metrics().book_cost, 1000.0);
+        });
+    }
+
+    #[test]
+    fn newer_data_file_is_refused() {
+        let data: serde_json::Value = serde_json::from_str(r#"{"version": 99}"#).unwrap();
+        assert!(matches!(
+            migrate(data, 99),
+            Err(StockoError::UnsupportedDataVersion(99))
+        ));
+    }
+
+    fn watching(symbols: &[&str]) -> StockCollections {
+        let mut collections = StockCollections::new();
+        for &symbol in symbols {
+            let stock = Stock {
+                symbol: String::from(symbol),
+                ..Default::default()
+            };
+            collections.watchlist.insert(String::from(symbol), stock);
+        }
+        collections
+    }
+
+    #[test]
+    fn move_adds_a_watched_stock_to_the_portfolio() {
+        with_data_path("move-fresh", |_| {
+            save_data(watching(&["AAPL", "MSFT"])).unwrap();
+            move_to_portfolio(String::from("AAPL"), 5.0, 20.0, today(), 1.0).unwrap();
+
+            let collections = load_data().unwrap();
+            assert!(!collections.watchlist.contains_key("AAPL"));
+            assert!(collections.watchlist.contains_key("MSFT"));
+            let orders = &collections.portfolio["AAPL"].orders;
+            assert_eq!(orders.len(), 1);
+            assert_close(orders[0].shares, 5.0);
+            assert_close(orders[0].fee, 1.0);
+        });
+    }
+
+    #[test]
+    fn move_merges_into_an_existing_holding() {
+        with_data_path("move-merge", |_| {
+            let mut collections = watching(&["AAPL"]);
+            let held = stock_with(vec![order(10.0, 10.0, "2024-01-01")]);
+            collections.portfolio.insert(String::from("AAPL"), held);
+            save_data(collections).unwrap();
+
+            move_to_portfolio(String::from("AAPL"), 10.0, 20.0, today(), 0.0).unwrap();
+
+            let collections = load_data().unwrap();
+            assert!(collections.watchlist.is_empty());
+            let metrics = collections.portfolio["AAPL"].calculate_order_metrics();
+            assert_close(metrics.total_shares, 20.0);
+            assert_close(metrics.average_cost, 15.0);
+        });
+    }
+
+    #[test]
+    fn move_rejects_negative_shares() {
+        with_data_path("move-negative", |_| {
+            save_data(watching(&["AAPL"])).unwrap();
+
+            let result = move_to_portfolio(String::from("AAPL"), -5.0, 10.0, today(), 0.0);
+            assert!(matches!(
+                result,
+                Err(StockoError::InvalidShareQuantity { .. })
+            ));
+
+            let collections = load_data().unwrap();
+            assert!(collections.watchlist.contains_key("AAPL"));
+            assert!(collections.portfolio.is_empty());
+        });
+    }
+
+    fn plain() -> RenderOptions {
+        RenderOptions {
+            color: ColorMode::Disabled,
+            plain: true,
+            precision: DEFAULT_PRECISION,
+        }
+    }
+
+    fn list_options(only: Collection) -> ListOptions {
+        ListOptions {
+            only,
+            base_currency: None,
+            archive_limit: None,
+            archive_page: 1,
+            sort: SortOrder {
+                field: SortField::Symbol,
+                descending: false,
+            },
+            offline: false,
+            benchmark: None,
+            method: CostMethod::Average,
+            change_days: 1,
+            refresh: None,
+        }
+    }
+
+    #[test]
+    fn offline_list_shows_the_stored_prices() {
+        let mut collections = StockCollections::new();
+        let mut stock = stock_with(vec![order(2.0, 10.0, "2024-01-01")]);
+        stock.last_quote = Some(LastQuote {
+            close: 12.5,
+            close_yesterday: 10.0,
+            date: String::from("2024-01-02"),
+            fetched_at: String::from("2024-01-02 16:05"),
+        });
+        collections.portfolio.insert(String::from("AAPL"), stock);
+
+        let fetcher = StubFetcher::default().with_closes("AAPL", &RISING);
+        let mut cache = QuoteCache::new(&fetcher);
+        cache.offline = true;
+        let options = list_options(Collection::Portfolio);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+
+        assert!(fetcher.fetched().is_empty());
+        let row = output.lines().find(|x| x.starts_with("AAPL\t")).unwrap();
+        assert!(row.contains("12.50"), "{}", row);
+        assert!(row.contains("25.00"), "{}", row);
+        assert!(output.ends_with("Offline, showing prices stored 2024-01-02 16:05 (stale)\n"));
+    }
+
+    #[test]
+    fn summary_adds_up_the_portfolio_and_archive() {
+        let mut collections = StockCollections::new();
+        let mut held = stock_with(vec![order(2.0, 10.0, "2024-01-01")]);
+        held.dividends.push(Dividend {
+            amount: 1.0,
+            date: String::from("2024-01-02"),
+        });
+        collections.portfolio.insert(String::from("AAPL"), held);
+        let unpriced = Stock {
+            symbol: String::from("NVDA"),
+            orders: vec![order(1.0, 500.0, "2024-01-01")],
+            ..Default::default()
+        };
+        collections.portfolio.insert(String::from("NVDA"), unpriced);
+        let mut closed = Stock {
+            symbol: String::from("MSFT"),
+            orders: vec![
+                order(1.0, 10.0, "2024-01-01"),
+                order(-1.0, 15.0, "2024-01-02"),
+            ],
+            ..Default::default()
+        };
+        closed.dividends.push(Dividend {
+            amount: 0.5,
+            date: String::from("2024-01-02"),
+        });
+        collections.archive.insert(String::from("MSFT"), closed);
+
+        // NVDA has no closes, so it is left out rather than failing the summary
+        let fetcher = StubFetcher::default().with_closes("AAPL", &RISING);
+        let mut cache = QuoteCache::new(&fetcher);
+        assert!(cache.prefetch(vec!["AAPL", "NVDA"], 2).is_err());
+        let output = collections
+            .render_summary(&mut cache, plain(), None, CostMethod::Average)
+            .unwrap();
+
+        let expected = [
+            "Book cost     20.00",
+            "Market value  22.00",
+            "Unrealized    +2.00 (+10.00%)",
+            "Realized      +5.00",
+            "Income        1.50",
+            "Total return  +8.50",
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn response_errors_map_to_their_kind() {
+        let parse = |body| parse_time_series_response("AAPL", Interval::Daily, body);
+
+        let note = r#"{"Note": "Thank you for using Alpha Vantage! 5 calls per minute"}"#;
+        assert!(
+            matches!(parse(note), Err(StockoError::RateLimited(ref x)) if x.contains("5 calls"))
+        );
+        let information = r#"{"Information": "Our standard API rate limit is 25 calls"}"#;
+        assert!(matches!(
+            parse(information),
+            Err(StockoError::RateLimited(_))
+        ));
+
+        let error = r#"{"Error Message": "Invalid API call."}"#;
+        assert!(matches!(
+            parse(error),
+            Err(StockoError::InvalidSymbol { ref symbol }) if symbol == "AAPL"
+        ));
+
+        // A weekly series where a daily one was asked for
+        let missing = r#"{"Meta Data": {}, "Weekly Time Series": {}}"#;
+        assert!(matches!(
+            parse(missing),
+            Err(StockoError::DeserializationError(_))
+        ));
+        assert!(matches!(
+            parse("<html>"),
+            Err(StockoError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn server_errors_are_retried() {
+        let unavailable = reqwest::StatusCode::ServiceUnavailable;
+        let result = check_status(unavailable);
+        assert!(matches!(result, Err(StockoError::ApiServerError(_))));
+        assert!(result.unwrap_err().is_transient());
+
+        assert!(check_status(reqwest::StatusCode::Ok).is_ok());
+        assert!(check_status(reqwest::StatusCode::NotFound).is_ok());
+    }
+
+    #[test]
+    fn selling_everything_with_yes_archives_without_asking() {
+        let fetcher = StubFetcher::default();
+        with_data_path("sell-yes", |_| {
+            save_data_file(&data_file_holding("AAPL")).unwrap();
+
+            let sell = order(-1.0, 12.0, "2024-02-01");
+            let mut answer = |_: &str| -> Result<bool, StockoError> { panic!("asked to confirm") };
+            process_order(
+                String::from("AAPL"),
+                None,
+                sell,
+                &fetcher,
+                true,
+                &mut answer,
+            )
+            .unwrap();
+
+            let collections = load_data().unwrap();
+            assert!(collections.portfolio.is_empty());
+            assert!(collections.archive.contains_key("AAPL"));
+        });
+    }
+
+    #[test]
+    fn declining_to_archive_leaves_the_portfolio_untouched() {
+        let fetcher = StubFetcher::default();
+        with_data_path("sell-declined", |path| {
+            save_data_file(&data_file_holding("AAPL")).unwrap();
+            let original = fs::read(path).unwrap();
+
+            let mut prompts = Vec::new();
+            let mut answer = |prompt: &str| {
+                prompts.push(String::from(prompt));
+                Ok(false)
+            };
+            let sell = order(-1.0, 12.0, "2024-02-01");
+            process_order(
+                String::from("AAPL"),
+                None,
+                sell,
+                &fetcher,
+                false,
+                &mut answer,
+            )
+            .unwrap();
+
+            assert_eq!(prompts, vec!["Fully sold AAPL; archive this position?"]);
+            assert_eq!(fs::read(path).unwrap(), original);
+        });
+    }
+
+    fn fetch_options() -> FetchOptions {
+        FetchOptions {
+            retries: 0,
+            interval: Interval::Daily,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    #[test]
+    fn refresh_redraws_until_stopped() {
+        let fetcher = StubFetcher::default().with_closes("AAPL", &RISING);
+        with_data_path("refresh", |_| {
+            save_data_file(&data_file_holding("AAPL")).unwrap();
+            let mut cache = QuoteCache::new(&fetcher);
+
+            // Asking for no interval at all still waits out the minimum between redraws
+            let minimum = Duration::from_millis(20);
+            let started = Instant::now();
+            refresh_list(
+                OutputFormat::Json,
+                plain(),
+                &list_options(Collection::Portfolio),
+                fetch_options(),
+                &mut cache,
+                Duration::ZERO,
+                minimum,
+                &|| fetcher.fetched().len() >= 3,
+            )
+            .unwrap();
+
+            assert_eq!(fetcher.fetched(), vec!["AAPL", "AAPL", "AAPL"]);
+            assert!(started.elapsed() >= minimum * 2);
+        });
+    }
+
+    /// The plain output fields of the row starting with `first`
+    fn row<'a>(output: &'a str, first: &str) -> Vec<&'a str> {
+        let line = output.lines().find(|x| x.split('\t').next() == Some(first));
+        line.unwrap_or_else(|| panic!("no {} row in {}", first, output))
+            .split('\t')
+            .collect()
+    }
+
+    /// Holding `shares` of `symbol` on `exchange`, bought at 10
+    fn holding(symbol: &str, exchange: Exchange, shares: f64) -> Stock {
+        Stock {
+            symbol: String::from(symbol),
+            exchange,
+            orders: vec![order(shares, 10.0, "2024-01-01")],
+            ..Default::default()
+        }
+    }
+
+    fn portfolio_of(stocks: Vec<Stock>) -> StockCollections {
+        let mut collections = StockCollections::new();
+        for stock in stocks {
+            collections.portfolio.insert(stock.symbol.clone(), stock);
+        }
+        collections
+    }
+
+    const WEIGHT: usize = 11;
+
+    #[test]
+    fn weights_add_up_to_the_whole_portfolio() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &RISING)
+            .with_closes("MSFT", &RISING)
+            .with_closes("NVDA", &RISING)
+            .with_closes("RY.TO", &RISING);
+        let collections = portfolio_of(vec![
+            holding("AAPL", Exchange::NASDAQ, 5.0),
+            holding("MSFT", Exchange::NASDAQ, 3.0),
+            holding("NVDA", Exchange::NASDAQ, 2.0),
+        ]);
+        let mut cache = QuoteCache::new(&fetcher);
+        let options = list_options(Collection::Portfolio);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+
+        assert_eq!(row(&output, "AAPL")[WEIGHT], "50.00%");
+        assert_eq!(row(&output, "MSFT")[WEIGHT], "30.00%");
+        assert_eq!(row(&output, "NVDA")[WEIGHT], "20.00%");
+        assert_eq!(row(&output, "Total")[WEIGHT], "100.00%");
+
+        // Without a conversion a Canadian holding can't be weighed against the others
+        let mut collections = collections;
+        let canadian = holding("RY.TO", Exchange::TSX, 1.0);
+        collections
+            .portfolio
+            .insert(String::from("RY.TO"), canadian);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+        for symbol in &["AAPL", "RY.TO", "USD subtotal", "CAD subtotal"] {
+            assert_eq!(row(&output, symbol)[WEIGHT], "");
+        }
+    }
+
+    #[test]
+    fn precision_applies_to_amounts_but_not_shares() {
+        let mut buy = order(0.125, 10.5, "2024-01-01");
+        buy.fee = 1.0;
+        assert_eq!(
+            buy.describe(plain()),
+            "2024-01-01  0.125 @ 10.50 + 1.00 fee"
+        );
+        let precise = RenderOptions {
+            precision: 4,
+            ..plain()
+        };
+        assert_eq!(
+            buy.describe(precise),
+            "2024-01-01  0.125 @ 10.5000 + 1.0000 fee"
+        );
+        assert_eq!(generate_shares_string(10.0), "10");
+        assert_eq!(generate_shares_string(0.1 + 0.2), "0.3");
+
+        let fetcher = StubFetcher::default().with_closes("AAPL", &RISING);
+        let collections = portfolio_of(vec![holding("AAPL", Exchange::NASDAQ, 0.125)]);
+        let options = list_options(Collection::Portfolio);
+        let mut cache = QuoteCache::new(&fetcher);
+        let output = render_list(&collections, &mut cache, precise, &options).unwrap();
+        let fields = row(&output, "AAPL");
+        assert_eq!(fields[2], "11.0000 (2024-01-02)");
+        assert_eq!(fields[4], "0.125");
+        assert_eq!(fields[5], "10.0000");
+
+        // Box drawing characters only appear when the table is drawn
+        let is_border = |c: char| ('\u{2500}'..='\u{257f}').contains(&c);
+        assert!(!output.chars().any(is_border));
+        let drawn = RenderOptions {
+            plain: false,
+            ..precise
+        };
+        let output = render_list(&collections, &mut cache, drawn, &options).unwrap();
+        assert!(output.chars().any(is_border));
+    }
+
+    #[test]
+    fn change_is_measured_over_the_lookback() {
+        let closes: Vec<(String, f64)> = (0..7)
+            .map(|x| (format!("2024-01-0{}", x + 1), 10.0 + x as f64))
+            .collect();
+        let closes: Vec<(&str, f64)> = closes.iter().map(|x| (x.0.as_str(), x.1)).collect();
+        let series = time_series(&closes);
+
+        let metrics = calculate_stock_metrics("AAPL", &series, 1).unwrap();
+        assert_close(metrics.change, 1.0);
+        assert_eq!(change_header(1, Interval::Daily), "Change");
+
+        let metrics = calculate_stock_metrics("AAPL", &series, 5).unwrap();
+        assert_close(metrics.close_yesterday, 11.0);
+        assert_eq!(
+            generate_period_change_string(&metrics, 5, Interval::Daily, plain()),
+            "+5.00 (+45.45%)"
+        );
+        assert_eq!(change_header(5, Interval::Daily), "Change (5d)");
+        assert_eq!(change_header(5, Interval::Weekly), "Change (5w)");
+        assert_eq!(change_header(5, Interval::Monthly), "Change (5mo)");
+
+        // Ten entries back goes past the start of the series, so the change says what it covers
+        let metrics = calculate_stock_metrics("AAPL", &series, 10).unwrap();
+        assert_eq!(metrics.period, 6);
+        assert_close(metrics.change, 6.0);
+        assert_eq!(
+            generate_period_change_string(&metrics, 10, Interval::Weekly, plain()),
+            "+6.00 (+60.00%) over 6w"
+        );
+    }
+
+    #[test]
+    fn exported_orders_read_back_as_recorded() {
+        let mut collections = portfolio_of(vec![holding("RY.TO", Exchange::TSX, 4.0)]);
+        let mut sell = order(-1.0, 12.5, "2024-03-01");
+        sell.fee = 4.95;
+        collections
+            .portfolio
+            .get_mut("RY.TO")
+            .unwrap()
+            .orders
+            .push(sell);
+        let closed = Stock {
+            symbol: String::from("MSFT"),
+            orders: vec![order(0.5, 300.0, "2024-01-02")],
+            exchange: Exchange::NASDAQ,
+            ..Default::default()
+        };
+        collections.archive.insert(String::from("MSFT"), closed);
+
+        let csv = orders_to_csv(&collections);
+        let rows: Vec<Vec<String>> = csv.lines().map(parse_csv_row).collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].join(","), EXPORT_HEADER);
+        assert_eq!(rows[1], ["MSFT", "NASDAQ", "2024-01-02", "0.5", "300", "0"]);
+        assert_eq!(rows[2], ["RY.TO", "TSX", "2024-01-01", "4", "10", "0"]);
+        assert_eq!(
+            rows[3],
+            ["RY.TO", "TSX", "2024-03-01", "-1", "12.5", "4.95"]
+        );
+    }
+
+    #[test]
+    fn export_only_writes_csv() {
+        assert!(matches!(
+            export(Some("json"), None),
+            Err(StockoError::UnsupportedFormat(ref x)) if x == "json"
+        ));
+        assert!(matches!(
+            export(Some("table"), None),
+            Err(StockoError::UnsupportedFormat(_))
+        ));
+    }
+
+    const IMPORT_CSV: &str = "\
+symbol,exchange,date,shares,share_price,fee
+AAPL,NASDAQ,2024-01-01,10,10,1
+MSFT,NASDAQ,2024-01-02,2,300,
+AAPL,NASDAQ,2024-02-01,-4,12,
+";
+
+    #[test]
+    fn import_applies_every_row() {
+        let fetcher = StubFetcher {
+            listings: vec!["AAPL", "MSFT"],
+            ..Default::default()
+        };
+        with_data_path("import", |path| {
+            let csv = path.with_file_name("orders.csv");
+            fs::write(&csv, IMPORT_CSV).unwrap();
+            import(csv.to_str().unwrap(), true, &fetcher).unwrap();
+
+            let collections = load_data().unwrap();
+            let apple = collections.portfolio["AAPL"].calculate_order_metrics();
+            assert_close(apple.total_shares, 6.0);
+            assert_close(apple.realized_gain, 4.0 * 12.0 - 4.0 * 10.1);
+            let microsoft = &collections.portfolio["MSFT"].orders;
+            assert_eq!(microsoft.len(), 1);
+            assert_eq!(microsoft[0].date.as_deref(), Some("2024-01-02"));
+            assert_close(microsoft[0].fee, 0.0);
+        });
+    }
+
+    #[test]
+    fn invalid_import_rows_are_reported_by_line() {
+        let fetcher = StubFetcher {
+            listings: vec!["AAPL", "MSFT"],
+            ..Default::default()
+        };
+        let csv = IMPORT_CSV.replace("2,300,", "two,300,");
+        with_data_path("import-invalid", |path| {
+            save_data_file(&data_file_holding("IBM")).unwrap();
+            let original = fs::read(path).unwrap();
+            let csv_path = path.with_file_name("orders.csv");
+            fs::write(&csv_path, csv).unwrap();
+            let csv_path = csv_path.to_str().unwrap();
+
+            // Strict stops at the MSFT row, the third line, before anything is saved
+            assert!(matches!(
+                import(csv_path, true, &fetcher),
+                Err(StockoError::InvalidImportRow { line: 3, ref cause }) if cause.contains("two")
+            ));
+            assert_eq!(fs::read(path).unwrap(), original);
+
+            import(csv_path, false, &fetcher).unwrap();
+            let collections = load_data().unwrap();
+            assert_eq!(collections.portfolio["AAPL"].orders.len(), 2);
+            assert!(!collections.portfolio.contains_key("MSFT"));
+            assert!(collections.portfolio.contains_key("IBM"));
+        });
+    }
+
+    #[test]
+    fn sparkline_scales_closes_between_the_lowest_and_highest() {
+        let rising: Vec<f64> = (0..8).map(|x| 10.0 + x as f64).collect();
+        assert_eq!(sparkline(&rising), "▁▂▃▄▅▆▇█");
+        assert_eq!(sparkline(&[5.0, 20.0, 12.5, 5.0]), "▁█▅▁");
+        assert_eq!(sparkline(&[3.0, 3.0, 3.0]), "▄▄▄");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn movers_are_ranked_by_change() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &[("2024-01-01", 10.0), ("2024-01-02", 11.0)])
+            .with_closes("MSFT", &[("2024-01-01", 10.0), ("2024-01-02", 13.0)])
+            .with_closes("NVDA", &[("2024-01-01", 10.0), ("2024-01-02", 8.0)])
+            .with_closes("IBM", &[("2024-01-01", 10.0), ("2024-01-02", 9.5)]);
+        let symbols = ["AAPL", "IBM", "MSFT", "NVDA", "SHOP"];
+        let collections = watching(&symbols);
+        let stocks = || collections.watchlist.values().collect::<Vec<_>>();
+        let mut cache = QuoteCache::new(&fetcher);
+        assert!(cache.prefetch(symbols.to_vec(), 2).is_err());
+
+        let ranked = |direction, limit| -> Vec<String> {
+            rank_movers(stocks(), &cache, direction, limit)
+                .into_iter()
+                .map(|x| x.0.symbol.clone())
+                .collect()
+        };
+        assert_eq!(ranked(MoverDirection::Top, 3), ["MSFT", "AAPL", "IBM"]);
+        assert_eq!(ranked(MoverDirection::Worst, 2), ["NVDA", "IBM"]);
+        // SHOP has no quote, so asking for more leaves it out
+        assert_eq!(ranked(MoverDirection::Top, 10).len(), 4);
+    }
+
+    #[test]
+    fn each_currency_gets_a_subtotal() {
+        let fetcher = StubFetcher {
+            rates: vec![((Currency::CAD, Currency::USD), 0.75)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        }
+        .with_closes("RY.TO", &RISING)
+        .with_closes("AAPL", &[("2024-01-01", 20.0), ("2024-01-02", 25.0)]);
+        let collections = portfolio_of(vec![
+            holding("RY.TO", Exchange::TSX, 4.0),
+            holding("AAPL", Exchange::NYSE, 2.0),
+        ]);
+        let mut cache = QuoteCache::new(&fetcher);
+        let mut options = list_options(Collection::Portfolio);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+
+        let cad = row(&output, "CAD subtotal");
+        assert_eq!(cad[2], "44.00");
+        assert_eq!(cad[3], "+4.00 (+10.00%)");
+        assert_eq!(cad[6], "40.00");
+        assert_eq!(cad[7], "+4.00 (+10.00%)");
+        let usd = row(&output, "USD subtotal");
+        assert_eq!(usd[2], "50.00");
+        assert_eq!(usd[3], "+10.00 (+25.00%)");
+        assert_eq!(usd[6], "20.00");
+        assert_eq!(usd[7], "+30.00 (+150.00%)");
+        // Without a conversion the two can't be added up
+        assert!(!output.lines().any(|x| x.starts_with("Total")));
+
+        options.base_currency = Some(Currency::USD);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+        assert_eq!(row(&output, "CAD subtotal")[2], "44.00");
+        assert_eq!(row(&output, "Total (USD)")[2], "83.00");
+    }
+
+    #[test]
+    fn clearing_the_watchlist_keeps_the_portfolio() {
+        with_data_path("clear", |_| {
+            let mut collections = watching(&["MSFT", "NVDA"]);
+            collections
+                .portfolio
+                .insert(String::from("AAPL"), holding("AAPL", Exchange::NASDAQ, 1.0));
+            save_data(collections).unwrap();
+
+            let options = ClearOptions {
+                skip_confirmation: true,
+                dry_run: false,
+                force: false,
+            };
+            clear(Collection::Watchlist, options).unwrap();
+
+            let collections = load_data().unwrap();
+            assert!(collections.watchlist.is_empty());
+            assert!(collections.portfolio.contains_key("AAPL"));
+
+            // Open positions need --force
+            let result = clear(Collection::Portfolio, options);
+            assert!(matches!(
+                result,
+                Err(StockoError::ClearPortfolioWithoutForce)
+            ));
+            assert!(load_data().unwrap().portfolio.contains_key("AAPL"));
+        });
+    }
+
+    #[test]
+    fn dry_run_clear_leaves_the_data_untouched() {
+        with_data_path("clear-dry-run", |path| {
+            let mut collections = watching(&["MSFT"]);
+            collections
+                .portfolio
+                .insert(String::from("AAPL"), holding("AAPL", Exchange::NASDAQ, 1.0));
+            save_data(collections).unwrap();
+            let original = fs::read(path).unwrap();
+
+            let options = ClearOptions {
+                skip_confirmation: true,
+                dry_run: true,
+                force: false,
+            };
+            clear(Collection::All, options).unwrap();
+            assert_eq!(fs::read(path).unwrap(), original);
+        });
+    }
+
+    #[test]
+    fn accounts_are_kept_apart() {
+        with_data_path("accounts", |_| {
+            save_data(watching(&["MSFT"])).unwrap();
+            assert_eq!(load_account("ira").unwrap().count(Collection::All), 0);
+
+            let ira = portfolio_of(vec![holding("AAPL", Exchange::NASDAQ, 3.0)]);
+            save_account("ira", ira).unwrap();
+            let mut default = load_data().unwrap();
+            default
+                .portfolio
+                .insert(String::from("NVDA"), holding("NVDA", Exchange::NASDAQ, 1.0));
+            save_data(default).unwrap();
+
+            let data = load_data_file().unwrap();
+            let mut names: Vec<&String> = data.accounts.keys().collect();
+            names.sort();
+            assert_eq!(names, ["default", "ira"]);
+
+            let ira = load_account("ira").unwrap();
+            assert!(ira.watchlist.is_empty());
+            assert_eq!(ira.portfolio.keys().collect::<Vec<_>>(), ["AAPL"]);
+            let default = load_data().unwrap();
+            assert_eq!(default.watchlist.keys().collect::<Vec<_>>(), ["MSFT"]);
+            assert_eq!(default.portfolio.keys().collect::<Vec<_>>(), ["NVDA"]);
+        });
+    }
+
+    #[test]
+    fn dividends_are_recorded_on_held_stocks() {
+        with_data_path("dividend", |path| {
+            save_data_file(&data_file_holding("AAPL")).unwrap();
+            add_dividend(String::from("AAPL"), 1.25, String::from("2024-02-01")).unwrap();
+            add_dividend(String::from("AAPL"), 0.75, String::from("2024-05-01")).unwrap();
+
+            let stock = &load_data().unwrap().portfolio["AAPL"];
+            assert_eq!(stock.dividends.len(), 2);
+            assert_eq!(stock.dividends[1].date, "2024-05-01");
+            assert_close(stock.dividend_income(), 2.0);
+
+            let saved = fs::read(path).unwrap();
+            let result = add_dividend(String::from("MSFT"), 1.0, today());
+            assert!(matches!(result, Err(StockoError::SymbolNotInPortfolio(_))));
+            assert_eq!(fs::read(path).unwrap(), saved);
+        });
+    }
+
+    #[test]
+    fn total_return_adds_gains_and_income() {
+        let mut stock = stock_with(vec![
+            order(10.0, 10.0, "2024-01-01"),
+            order(-4.0, 15.0, "2024-01-02"),
+        ]);
+        stock.dividends.push(Dividend {
+            amount: 2.5,
+            date: String::from("2024-01-02"),
+        });
+        let holdings = [Holding {
+            stock: &stock,
+            metrics: calculate_stock_metrics("AAPL", &time_series(&RISING), 1).unwrap(),
+            order_metrics: Some(stock.calculate_order_metrics()),
+            closes: Vec::new(),
+        }];
+
+        // 6 shares up 1 each, 4 sold 5 above cost and the dividend
+        let totals = PortfolioTotals::new(&holdings, None);
+        assert_close(totals.gain, 6.0);
+        assert_close(totals.realized, 20.0);
+        assert_close(totals.income, 2.5);
+        assert_close(totals.total_return(), 28.5);
+    }
+
+    #[test]
+    fn footer_shows_when_the_oldest_price_was_fetched() {
+        use chrono::TimeZone;
+
+        let fetcher = StubFetcher::default();
+        let collections = portfolio_of(vec![
+            holding("AAPL", Exchange::NASDAQ, 1.0),
+            holding("MSFT", Exchange::NASDAQ, 1.0),
+        ]);
+        let mut cache = QuoteCache::new(&fetcher);
+        for &(symbol, minute) in &[("AAPL", 7), ("MSFT", 5)] {
+            let fetched_at = Local.ymd(2024, 1, 2).and_hms(16, minute, 0);
+            let entry = (time_series(&RISING), fetched_at);
+            cache.series.insert(String::from(symbol), entry);
+        }
+
+        let options = list_options(Collection::Portfolio);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+        assert!(fetcher.fetched().is_empty());
+        assert!(output.ends_with("\nPrices as of 2024-01-02 16:05 (delayed)\n"));
+    }
+
+    #[test]
+    fn benchmark_column_compares_against_the_benchmark() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &RISING)
+            .with_closes("SPY", &[("2024-01-01", 100.0), ("2024-01-02", 104.0)]);
+        let collections = portfolio_of(vec![holding("AAPL", Exchange::NASDAQ, 1.0)]);
+        let mut options = list_options(Collection::Portfolio);
+        options.benchmark = Some(String::from("SPY"));
+
+        let mut cache = QuoteCache::new(&fetcher);
+        let benchmark = fetch_benchmark(&mut cache, "SPY").unwrap();
+        assert_close(benchmark.change_percentage, 4.0);
+
+        // AAPL rose 10% against the benchmark's 4%
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+        assert_eq!(row(&output, "Symbol").last(), Some(&"vs Bench"));
+        assert_eq!(row(&output, "AAPL")[WEIGHT + 1], "+6.00%");
+
+        // Without a benchmark quote the column is left out rather than failing the list
+        options.benchmark = Some(String::from("QQQ"));
+        let mut cache = QuoteCache::new(&fetcher);
+        assert!(fetch_benchmark(&mut cache, "QQQ").is_none());
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+        assert!(!output.contains("vs Bench"));
+        assert_eq!(row(&output, "AAPL").len(), WEIGHT + 1);
+    }
+
+    #[test]
+    fn rename_keeps_the_orders() {
+        with_data_path("rename", |_| {
+            let mut stock = holding("FB", Exchange::NASDAQ, 2.0);
+            stock.orders.push(order(-1.0, 12.0, "2024-02-01"));
+            let mut collections = watching(&["MSFT"]);
+            collections.portfolio.insert(String::from("FB"), stock);
+            save_data(collections).unwrap();
+
+            rename(String::from("FB"), String::from("META")).unwrap();
+
+            let collections = load_data().unwrap();
+            assert!(!collections.portfolio.contains_key("FB"));
+            let stock = &collections.portfolio["META"];
+            assert_eq!(stock.symbol, "META");
+            assert_eq!(stock.orders.len(), 2);
+            assert_close(stock.calculate_order_metrics().total_shares, 1.0);
+
+            assert!(matches!(
+                rename(String::from("META"), String::from("MSFT")),
+                Err(StockoError::SymbolExists(_))
+            ));
+            assert!(matches!(
+                rename(String::from("FB"), String::from("FBOOK")),
+                Err(StockoError::SymbolNotFound(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn targets_are_reached_at_or_above_the_price() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &RISING)
+            .with_closes("MSFT", &RISING)
+            .with_closes("NVDA", &RISING);
+        with_data_path("target", |_| {
+            save_data(watching(&["AAPL", "MSFT", "NVDA"])).unwrap();
+            set_target(String::from("AAPL"), Some(10.5)).unwrap();
+            set_target(String::from("MSFT"), Some(11.0)).unwrap();
+            set_target(String::from("NVDA"), Some(12.0)).unwrap();
+            assert!(matches!(
+                set_target(String::from("IBM"), Some(1.0)),
+                Err(StockoError::UntrackedSymbol(_))
+            ));
+
+            let collections = load_data().unwrap();
+            let mut cache = QuoteCache::new(&fetcher);
+            let options = list_options(Collection::Watchlist);
+            let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+            assert_eq!(row(&output, "*AAPL").len(), 4);
+            assert_eq!(row(&output, "*MSFT").len(), 4);
+            assert_eq!(row(&output, "NVDA").len(), 4);
+            let reached: Vec<&str> = output.lines().filter(|x| x.contains("reached")).collect();
+            assert_eq!(
+                reached,
+                [
+                    "AAPL reached target 10.50 (now 11.00)",
+                    "MSFT reached target 11.00 (now 11.00)",
+                ]
+            );
+
+            set_target(String::from("AAPL"), None).unwrap();
+            assert_eq!(load_data().unwrap().watchlist["AAPL"].target_price, None);
+        });
+    }
+
+    #[test]
+    fn holdings_are_sorted_by_the_chosen_field() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &[("2024-01-01", 10.0), ("2024-01-02", 20.0)])
+            .with_closes("MSFT", &RISING)
+            .with_closes("NVDA", &[("2024-01-01", 50.0), ("2024-01-02", 40.0)]);
+        let collections = portfolio_of(vec![
+            holding("AAPL", Exchange::NASDAQ, 1.0),
+            holding("MSFT", Exchange::NASDAQ, 10.0),
+            holding("NVDA", Exchange::NASDAQ, 2.0),
+        ]);
+        let mut cache = QuoteCache::new(&fetcher);
+
+        let mut sorted = |field, descending| {
+            let mut options = list_options(Collection::Portfolio);
+            options.sort = SortOrder { field, descending };
+            let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+            output
+                .lines()
+                .filter_map(|x| x.split('\t').next())
+                .filter(|x| collections.portfolio.contains_key(*x))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sorted(SortField::Symbol, false), ["AAPL", "MSFT", "NVDA"]);
+        assert_eq!(sorted(SortField::Symbol, true), ["NVDA", "MSFT", "AAPL"]);
+        assert_eq!(sorted(SortField::Price, false), ["MSFT", "AAPL", "NVDA"]);
+        assert_eq!(sorted(SortField::Change, false), ["NVDA", "MSFT", "AAPL"]);
+        // AAPL and MSFT are both up 10, so they are in symbol order
+        assert_eq!(sorted(SortField::Gain, false), ["AAPL", "MSFT", "NVDA"]);
+        assert_eq!(sorted(SortField::Gain, true), ["NVDA", "MSFT", "AAPL"]);
+        assert_eq!(sorted(SortField::Value, true), ["MSFT", "NVDA", "AAPL"]);
+    }
+
+    #[test]
+    fn search_lists_each_match() {
+        let body = r#"{"bestMatches": [
+            {"1. symbol": "SHOP", "2. name": "Shopify Inc", "3. type": "Equity",
+             "4. region": "United States", "8. currency": "USD"},
+            {"1. symbol": "SHOP.TRT", "2. name": "Shopify Inc", "3. type": "Equity",
+             "4. region": "Toronto", "8. currency": "CAD"}
+        ]}"#;
+        let matches = parse_symbol_search_response(body).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].symbol, "SHOP.TRT");
+        assert_eq!(matches[1].name, "Shopify Inc");
+        assert_eq!(matches[1].region, "Toronto");
+        assert_eq!(matches[1].currency, "CAD");
+
+        let fetcher = StubFetcher {
+            listings: vec!["SHOP", "SHOP.TRT"],
+            ..Default::default()
+        };
+        let output = render_search("shop", OutputFormat::Table, plain(), &fetcher).unwrap();
+        let symbols: Vec<&str> = output
+            .lines()
+            .filter_map(|x| x.split('\t').next())
+            .collect();
+        assert_eq!(symbols, ["Symbol", "SHOP", "SHOP.TRT"]);
+    }
+
+    #[test]
+    fn search_without_matches_says_so() {
+        let body = r#"{"bestMatches": []}"#;
+        assert!(parse_symbol_search_response(body).unwrap().is_empty());
+
+        let fetcher = StubFetcher::default();
+        let output = render_search("zzzz", OutputFormat::Table, plain(), &fetcher).unwrap();
+        assert_eq!(output, "No symbols match zzzz\n");
+    }
+
+    #[test]
+    fn remove_order_counts_orders_by_date() {
+        with_data_path("remove-order", |path| {
+            // Recorded out of date order, so the middle order by date is the last one recorded
+            let stock = stock_with(vec![
+                order(3.0, 30.0, "2024-03-01"),
+                order(1.0, 10.0, "2024-01-01"),
+                order(2.0, 20.0, "2024-02-01"),
+            ]);
+            save_data(portfolio_of(vec![stock])).unwrap();
+
+            remove_order(String::from("AAPL"), 1, plain()).unwrap();
+            let orders = &load_data().unwrap().portfolio["AAPL"].orders;
+            let dates: Vec<_> = orders.iter().map(|x| x.date.as_deref().unwrap()).collect();
+            assert_eq!(dates, ["2024-03-01", "2024-01-01"]);
+
+            let saved = fs::read(path).unwrap();
+            assert!(matches!(
+                remove_order(String::from("AAPL"), 2, plain()),
+                Err(StockoError::InvalidOrderIndex { index: 2, .. })
+            ));
+            assert_eq!(fs::read(path).unwrap(), saved);
+        });
+    }
+
+    #[test]
+    fn fees_are_part_of_the_cost() {
+        let mut buy = order(10.0, 10.0, "2024-01-01");
+        buy.fee = 5.0;
+        let mut sell = order(-5.0, 12.0, "2024-02-01");
+        sell.fee = 2.0;
+
+        let metrics = stock_with(vec![buy.clone()]).calculate_order_metrics();
+        assert_close(metrics.book_cost, 105.0);
+        assert_close(metrics.average_cost, 10.5);
+
+        // The sell fee comes out of its proceeds rather than the cost of what is left
+        let stock = stock_with(vec![buy, sell]);
+        let metrics = stock.calculate_order_metrics();
+        assert_close(metrics.average_cost, 10.5);
+        assert_close(metrics.realized_gain, 60.0 - 2.0 - 52.5);
+        let (book_cost, realized) = stock.lot_cost_basis(CostMethod::Fifo);
+        assert_close(book_cost, 52.5);
+        assert_close(realized, 5.5);
+    }
+
+    #[test]
+    fn each_interval_has_its_endpoint_and_series() {
+        let expected = [
+            (
+                None,
+                Interval::Daily,
+                "TIME_SERIES_DAILY",
+                "Time Series (Daily)",
+            ),
+            (
+                Some("weekly"),
+                Interval::Weekly,
+                "TIME_SERIES_WEEKLY",
+                "Weekly Time Series",
+            ),
+            (
+                Some("monthly"),
+                Interval::Monthly,
+                "TIME_SERIES_MONTHLY",
+                "Monthly Time Series",
+            ),
+        ];
+        for &(name, interval, function, series_key) in &expected {
+            assert_eq!(Interval::from_name(name), interval);
+            assert_eq!(interval.function(), function);
+            assert_eq!(interval.series_key(), series_key);
+
+            // Each interval only reads the entries under its own key
+            let entry = r#"{"1. open": "1", "2. high": "1", "3. low": "1", "4. close": "1",
+                "5. volume": "1"}"#;
+            let body = format!(r#"{{"{}": {{"2024-01-05": {}}}}}"#, series_key, entry);
+            assert_eq!(
+                parse_time_series_response("AAPL", interval, &body)
+                    .unwrap()
+                    .len(),
+                1
+            );
+            for &(_, other, _, _) in expected.iter().filter(|x| x.1 != interval) {
+                assert!(parse_time_series_response("AAPL", other, &body).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn quote_shows_the_latest_close() {
+        let fetcher = StubFetcher::default().with_closes("AAPL", &RISING);
+        let quote = |format| render_quote(String::from("AAPL"), format, plain(), &fetcher);
+
+        let output = quote(OutputFormat::Table).unwrap();
+        assert_eq!(
+            row(&output, "AAPL"),
+            ["AAPL", "11.00 (2024-01-02)", "+1.00 (+10.00%)"]
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&quote(OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["close_today"], 11.0);
+        assert_eq!(json["as_of"], "2024-01-02");
+
+        assert!(matches!(
+            render_quote(String::from("MSFT"), OutputFormat::Table, plain(), &fetcher),
+            Err(StockoError::InvalidSymbol { .. })
+        ));
+    }
+
+    #[test]
+    fn currency_defaults_to_the_exchange() {
+        let expected = [
+            (Exchange::TSX, Currency::CAD),
+            (Exchange::TSXV, Currency::CAD),
+            (Exchange::NYSE, Currency::USD),
+            (Exchange::NASDAQ, Currency::USD),
+            (Exchange::LSE, Currency::GBP),
+            (Exchange::ASX, Currency::AUD),
+        ];
+        for &(ref exchange, currency) in &expected {
+            assert_eq!(exchange.currency(), currency);
+            assert_eq!(holding("X", exchange.clone(), 1.0).currency(), currency);
+        }
+
+        // A currency set on the stock wins over its exchange's
+        let mut listed = holding("SHOP", Exchange::TSX, 1.0);
+        listed.currency = Some(Currency::USD);
+        assert_eq!(listed.currency(), Currency::USD);
+    }
+
+    #[test]
+    fn converted_totals_add_up_every_currency() {
+        let rates = vec![
+            ((Currency::CAD, Currency::USD), 0.75),
+            ((Currency::AUD, Currency::USD), 0.5),
+        ];
+        let fetcher = StubFetcher {
+            rates: rates.into_iter().collect(),
+            ..Default::default()
+        }
+        .with_closes("RY.TO", &RISING)
+        .with_closes("BHP.AX", &RISING)
+        .with_closes("AAPL", &[("2024-01-01", 20.0), ("2024-01-02", 25.0)]);
+        let collections = portfolio_of(vec![
+            holding("RY.TO", Exchange::TSX, 4.0),
+            holding("BHP.AX", Exchange::ASX, 10.0),
+            holding("AAPL", Exchange::NASDAQ, 2.0),
+        ]);
+        let currencies = [Currency::CAD, Currency::AUD, Currency::USD];
+        let conversion = Conversion::new(Currency::USD, &currencies, &fetcher).unwrap();
+        assert_close(conversion.convert(10.0, Currency::USD), 10.0);
+
+        let mut cache = QuoteCache::new(&fetcher);
+        let (holdings, _) = collections.portfolio_holdings(&mut cache, CostMethod::Average);
+        let totals = PortfolioTotals::new(&holdings, Some(&conversion));
+        // 44 CAD, 110 AUD and 50 USD worth, bought for 40 CAD, 100 AUD and 20 USD
+        assert_close(totals.value, 44.0 * 0.75 + 110.0 * 0.5 + 50.0);
+        assert_close(totals.book_cost, 40.0 * 0.75 + 100.0 * 0.5 + 20.0);
+    }
+
+    #[test]
+    fn totals_row_adds_up_the_holdings() {
+        let fetcher = StubFetcher::default()
+            .with_closes("AAPL", &RISING)
+            .with_closes("MSFT", &[("2024-01-01", 20.0), ("2024-01-02", 25.0)]);
+        let collections = portfolio_of(vec![
+            holding("AAPL", Exchange::NASDAQ, 2.0),
+            holding("MSFT", Exchange::NASDAQ, 3.0),
+        ]);
+        let mut cache = QuoteCache::new(&fetcher);
+        let options = list_options(Collection::Portfolio);
+        let output = render_list(&collections, &mut cache, plain(), &options).unwrap();
+
+        // The day change is each close's change times the shares held, 2 × 1 + 3 × 5
+        let total = row(&output, "Total");
+        assert_eq!(total[2], "97.00");
+        assert_eq!(total[3], "+17.00 (+21.25%)");
+        assert_eq!(total[6], "50.00");
+        assert_eq!(total[7], "+47.00 (+94.00%)");
+        assert_eq!(total[10], "+47.00");
+    }
+
+    #[test]
+    fn unwatch_removes_a_watched_symbol() {
+        let fetcher = StubFetcher {
+            listings: vec!["MSFT", "NVDA"],
+            ..Default::default()
+        }
+        .with_closes("MSFT", &RISING)
+        .with_closes("NVDA", &RISING);
+        with_data_path("unwatch", |_| {
+            watch(String::from("MSFT"), None, &fetcher).unwrap();
+            watch(String::from("NVDA"), None, &fetcher).unwrap();
+
+            unwatch(String::from("MSFT")).unwrap();
+            let collections = load_data().unwrap();
+            assert_eq!(collections.watchlist.keys().collect::<Vec<_>>(), ["NVDA"]);
+
+            assert!(matches!(
+                unwatch(String::from("MSFT")),
+                Err(StockoError::SymbolNotInWatchlist(_))
+            ));
+        });
+    }
 }
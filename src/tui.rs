@@ -0,0 +1,471 @@
+use std::io::{self, Read, Write};
+use std::mem;
+
+use ansi_term::Colour::{Green, Red};
+use ansi_term::Style;
+
+use libc;
+
+use super::{
+    calculate_stock_metrics, generate_change_string, generate_gain_string, load_data,
+    recent_closes, Collection, FetchOptions, Fetcher, QuoteCache, RenderOptions, Stock,
+    StockCollections, StockMetrics, StockoError, SPARKLINE_CHARS,
+};
+
+const TABS: [Collection; 3] = [
+    Collection::Portfolio,
+    Collection::Watchlist,
+    Collection::Archive,
+];
+
+const LIST_WIDTH: usize = 30;
+const CHART_HEIGHT: usize = 8;
+const CHART_HISTORY: usize = 120;
+
+struct Quote {
+    metrics: StockMetrics,
+    closes: Vec<f64>,
+}
+
+struct Entry {
+    stock: Stock,
+    /// Archived stocks are never fetched so they have no quote
+    quote: Option<Result<Quote, String>>,
+}
+
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Quit,
+    Other,
+}
+
+struct Dashboard {
+    render: RenderOptions,
+    tabs: Vec<Vec<Entry>>,
+    tab: usize,
+    selected: [usize; 3],
+}
+
+impl Dashboard {
+    fn new(
+        collections: &StockCollections,
+        render: RenderOptions,
+        options: FetchOptions,
+        fetcher: &dyn Fetcher,
+    ) -> Dashboard {
+        let mut cache = QuoteCache::new(fetcher);
+        // Failed symbols are fetched again below so every entry can show its own error
+        let symbols = collections
+            .portfolio
+            .keys()
+            .chain(collections.watchlist.keys());
+        let _ = cache.prefetch(symbols.map(|x| x.as_str()), options.concurrency);
+        let tabs = TABS
+            .iter()
+            .map(|&tab| {
+                let (stocks, fetch) = match tab {
+                    Collection::Portfolio => (&collections.portfolio, true),
+                    Collection::Watchlist => (&collections.watchlist, true),
+                    _ => (&collections.archive, false),
+                };
+
+                let mut stocks: Vec<&Stock> = stocks.values().collect();
+                stocks.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+                stocks
+                    .into_iter()
+                    .map(|stock| Entry {
+                        stock: stock.clone(),
+                        quote: if fetch {
+                            Some(fetch_quote(&mut cache, &stock.symbol))
+                        } else {
+                            None
+                        },
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Dashboard {
+            render,
+            tabs,
+            tab: 0,
+            selected: [0; 3],
+        }
+    }
+
+    fn handle_key(&mut self, key: &Key) {
+        let count = self.tabs[self.tab].len();
+        let selected = &mut self.selected[self.tab];
+        match *key {
+            Key::Up => *selected = selected.saturating_sub(1),
+            Key::Down if *selected + 1 < count => *selected += 1,
+            Key::Left => self.tab = (self.tab + TABS.len() - 1) % TABS.len(),
+            Key::Right => self.tab = (self.tab + 1) % TABS.len(),
+            _ => {}
+        }
+    }
+
+    fn render(&self, width: usize, height: usize) -> String {
+        let detail_width = width.saturating_sub(LIST_WIDTH + 1);
+        let body_height = height.saturating_sub(4);
+
+        let list = self.render_list(body_height);
+        let detail = match self.tabs[self.tab].get(self.selected[self.tab]) {
+            Some(entry) => render_detail(TABS[self.tab], entry, detail_width, self.render),
+            None => vec![String::from(" Nothing here yet")],
+        };
+
+        let mut lines = vec![self.render_tabs(), "─".repeat(width)];
+        for i in 0..body_height {
+            lines.push(format!(
+                "{}│{}",
+                pad(list.get(i).map(|x| x.as_str()).unwrap_or(""), LIST_WIDTH),
+                detail.get(i).map(|x| x.as_str()).unwrap_or("")
+            ));
+        }
+        lines.push("─".repeat(width));
+        lines.push(String::from(" ←/→ switch tab   ↑/↓ select stock   q quit"));
+
+        format!("\x1b[H{}\x1b[K\x1b[J", lines.join("\x1b[K\r\n"))
+    }
+
+    fn render_tabs(&self) -> String {
+        TABS.iter()
+            .enumerate()
+            .map(|(i, &tab)| {
+                let title = format!(" {} ", tab_title(tab));
+                if i == self.tab {
+                    Style::new().reverse().paint(title).to_string()
+                } else {
+                    title
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("│")
+    }
+
+    fn render_list(&self, height: usize) -> Vec<String> {
+        let entries = &self.tabs[self.tab];
+        let selected = self.selected[self.tab];
+        let offset = (selected + 1).saturating_sub(height);
+
+        entries
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(height)
+            .map(|(i, entry)| {
+                let (value, up) = match entry.quote {
+                    Some(Ok(ref quote)) => (
+                        format!(
+                            "{:>9.2} {:>+7.2}%",
+                            quote.metrics.close_today, quote.metrics.change_percentage
+                        ),
+                        quote.metrics.change >= 0.0,
+                    ),
+                    Some(Err(_)) => (String::from("unavailable"), true),
+                    None => {
+                        let (gain, _) = entry.stock.calculate_order_metrics().closed_gain();
+                        (format!("{:>+18.2}", gain), gain >= 0.0)
+                    }
+                };
+
+                let line = pad(&format!(" {:<10}{}", entry.stock.symbol, value), LIST_WIDTH);
+                if i == selected {
+                    Style::new().reverse().paint(line).to_string()
+                } else if up {
+                    self.render.paint(Green, line)
+                } else {
+                    self.render.paint(Red, line)
+                }
+            })
+            .collect()
+    }
+}
+
+fn render_detail(
+    tab: Collection,
+    entry: &Entry,
+    width: usize,
+    render: RenderOptions,
+) -> Vec<String> {
+    let stock = &entry.stock;
+    let order_metrics = stock.calculate_order_metrics();
+
+    let mut lines = vec![
+        format!(" {} ({:?})", stock.symbol, stock.exchange),
+        String::new(),
+    ];
+
+    match entry.quote {
+        Some(Ok(ref quote)) => {
+            let metrics = &quote.metrics;
+            lines.push(format!(
+                " {:<12}{}",
+                "Price",
+                render.number(metrics.close_today)
+            ));
+            lines.push(format!(" {:<12}{}", "As of", metrics.date));
+            lines.push(format!(
+                " {:<12}{}",
+                "Change",
+                generate_change_string(metrics, render)
+            ));
+
+            if let Collection::Portfolio = tab {
+                let (gain, overall_gain) = order_metrics.unrealized_gain(metrics.close_today);
+                lines.push(format!(" {:<12}{}", "Shares", order_metrics.total_shares));
+                lines.push(format!(
+                    " {:<12}{}",
+                    "Avg Cost",
+                    render.number(order_metrics.average_cost)
+                ));
+                lines.push(format!(
+                    " {:<12}{}",
+                    "Book Cost",
+                    render.number(order_metrics.book_cost)
+                ));
+                lines.push(format!(
+                    " {:<12}{}",
+                    "Total Gain",
+                    generate_gain_string(gain, overall_gain, render)
+                ));
+            }
+        }
+        Some(Err(ref e)) => lines.push(truncate(&format!(" Failed to fetch quote: {}", e), width)),
+        None => {
+            let (gain, gain_percentage) = order_metrics.closed_gain();
+            lines.push(format!(
+                " {:<12}{}",
+                "Gain",
+                generate_gain_string(gain, gain_percentage, render)
+            ));
+        }
+    }
+
+    if !stock.orders.is_empty() {
+        lines.push(String::new());
+        lines.push(String::from(" Orders"));
+        // Numbered the way remove-order counts them
+        for (index, order) in stock.orders_by_date().into_iter().enumerate() {
+            lines.push(format!("   {:<3}{}", index, order.describe(render)));
+        }
+    }
+
+    if let Some(Ok(ref quote)) = entry.quote {
+        let closes = &quote.closes[quote.closes.len().saturating_sub(width.saturating_sub(2))..];
+        let high = closes.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let low = closes.iter().cloned().fold(f64::INFINITY, f64::min);
+
+        lines.push(String::new());
+        lines.push(format!(" High {}", render.number(high)));
+        lines.extend(
+            chart(closes, CHART_HEIGHT, render)
+                .into_iter()
+                .map(|x| format!(" {}", x)),
+        );
+        lines.push(format!(" Low  {}", render.number(low)));
+    }
+
+    lines
+}
+
+/// Draws `closes` as a bar chart `height` rows tall, using the partial block characters to
+/// get eight steps of resolution per row
+fn chart(closes: &[f64], height: usize, render: RenderOptions) -> Vec<String> {
+    let min = closes.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = closes.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    let steps = SPARKLINE_CHARS.len();
+    let top = (height * steps - 1) as f64;
+
+    // Every close gets at least one step so the lowest value is still visible
+    let levels: Vec<usize> = closes
+        .iter()
+        .map(|close| {
+            if range > 0.0 {
+                ((close - min) / range * top).round() as usize + 1
+            } else {
+                height * steps / 2
+            }
+        })
+        .collect();
+
+    let up = match (closes.first(), closes.last()) {
+        (Some(first), Some(last)) => last >= first,
+        _ => true,
+    };
+
+    (0..height)
+        .map(|row| {
+            let floor = (height - 1 - row) * steps;
+            let line: String = levels
+                .iter()
+                .map(|&level| {
+                    if level >= floor + steps {
+                        SPARKLINE_CHARS[steps - 1]
+                    } else if level > floor {
+                        SPARKLINE_CHARS[level - floor - 1]
+                    } else {
+                        ' '
+                    }
+                })
+                .collect();
+
+            if up {
+                render.paint(Green, line)
+            } else {
+                render.paint(Red, line)
+            }
+        })
+        .collect()
+}
+
+fn fetch_quote(cache: &mut QuoteCache, symbol: &str) -> Result<Quote, String> {
+    let time_series = cache.get(symbol).map_err(|e| e.to_string())?;
+    let closes = recent_closes(time_series, CHART_HISTORY);
+    Ok(Quote {
+        metrics: calculate_stock_metrics(symbol, time_series, 1).map_err(|e| e.to_string())?,
+        closes,
+    })
+}
+
+fn tab_title(tab: Collection) -> &'static str {
+    match tab {
+        Collection::Portfolio => "Portfolio",
+        Collection::Watchlist => "Watch List",
+        _ => "Archive",
+    }
+}
+
+/// Number of columns `text` takes up on screen, ignoring any colour escape sequences
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        if in_escape {
+            in_escape = c != 'm';
+        } else if c == '\x1b' {
+            in_escape = true;
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(visible_width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+fn terminal_size() -> (usize, usize) {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+}
+
+fn read_key() -> Result<Key, StockoError> {
+    let mut buf = [0u8; 8];
+    let read = io::stdin()
+        .read(&mut buf)
+        .map_err(|e| StockoError::ReadInputError(e.to_string()))?;
+
+    // Arrow keys arrive as a single escape sequence, a lone escape is the escape key itself
+    Ok(match buf[..read] {
+        [27, b'[', b'A'] | [b'k'] => Key::Up,
+        [27, b'[', b'B'] | [b'j'] => Key::Down,
+        [27, b'[', b'D'] | [b'h'] => Key::Left,
+        [27, b'[', b'C'] | [b'l'] | [b'\t'] => Key::Right,
+        [b'q'] | [27] | [3] => Key::Quit,
+        _ => Key::Other,
+    })
+}
+
+/// Puts the terminal into raw mode on the alternate screen, restoring it when dropped
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> Result<RawTerminal, StockoError> {
+        let original = unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(StockoError::TerminalError(
+                    io::Error::last_os_error().to_string(),
+                ));
+            }
+
+            // Signals are disabled so Ctrl-C goes through read_key and the terminal is restored
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(StockoError::TerminalError(
+                    io::Error::last_os_error().to_string(),
+                ));
+            }
+            original
+        };
+
+        let terminal = RawTerminal { original };
+        write_screen("\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = write_screen("\x1b[?25h\x1b[?1049l");
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+fn write_screen(text: &str) -> Result<(), StockoError> {
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(text.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|e| StockoError::TerminalError(e.to_string()))
+}
+
+pub fn run(
+    render: RenderOptions,
+    options: FetchOptions,
+    fetcher: &dyn Fetcher,
+) -> Result<(), StockoError> {
+    let collections = load_data()?;
+
+    println!("Fetching quotes...");
+    let mut dashboard = Dashboard::new(&collections, render, options, fetcher);
+
+    let _terminal = RawTerminal::enable()?;
+    loop {
+        let (width, height) = terminal_size();
+        write_screen(&dashboard.render(width, height))?;
+
+        match read_key()? {
+            Key::Quit => break,
+            key => dashboard.handle_key(&key),
+        }
+    }
+
+    Ok(())
+}